@@ -0,0 +1,73 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::args::Args;
+
+/// Something unusual about a group worth pointing out
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AnomalyKind {
+    /// A template which first appeared after the warmup, or which a loaded baseline didn't have
+    NewTemplate,
+    /// The group's recent rate jumped well above its moving average
+    RateSpike,
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnomalyKind::NewTemplate => f.write_str("new template"),
+            AnomalyKind::RateSpike => f.write_str("rate spike"),
+        }
+    }
+}
+
+/// An anomaly as it was detected, reported as an event by headless mode
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Anomaly {
+    pub at: DateTime<Utc>,
+    pub kind: AnomalyKind,
+    pub uid: String,
+    pub template: String,
+    /// Lines per second over roughly the last minute
+    pub rate: f64,
+    /// Lines per second over roughly the last fifteen minutes
+    pub average: f64,
+}
+
+/// Thresholds for flagging groups
+#[derive(Clone, Debug)]
+pub(crate) struct AnomalyConfig {
+    /// Groups created this soon after startup aren't new, and younger groups can't spike
+    pub warmup: Duration,
+    /// How many times its moving average a group's recent rate must reach to spike
+    pub spike_factor: f64,
+    /// Recent rate, in lines per second, below which a group never spikes
+    pub spike_min_rate: f64,
+}
+
+impl AnomalyConfig {
+    pub(crate) fn from_args(args: &Args) -> Self {
+        Self {
+            warmup: args.warmup,
+            spike_factor: args.spike_factor,
+            spike_min_rate: args.spike_min_rate,
+        }
+    }
+
+    pub(crate) fn is_spike(&self, rate: f64, average: f64) -> bool {
+        rate >= self.spike_min_rate && rate > average * self.spike_factor
+    }
+}
@@ -8,23 +8,46 @@
 // Server Side Public License along with this program.
 // If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
 
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use anyhow::Error;
 use drain_flow::SimpleDrain;
 use parking_lot::{Mutex, RwLock};
-use tokio::{sync::mpsc, task};
-use tracing::instrument;
+use tokio::{sync::mpsc, task, task::JoinHandle};
+use tracing::{debug, info, instrument};
 
 #[cfg(feature = "aws")]
 use crate::sources::aws;
 use crate::{
+    anomaly::AnomalyConfig,
     args::Args,
-    sources::{file::FileReader, LogReader},
+    compare::Side,
+    groups::{GroupSummary, GroupTracker},
+    masks,
+    metrics::Metrics,
+    pipeline::{
+        filter::Filter,
+        multiline::{combine, Combiner, MultilineConfig},
+        parse::Parser,
+        transform,
+        Stage,
+    },
+    sources::{
+        file::FileReader,
+        record_channel,
+        socket::SocketReader,
+        stdin::StdinReader,
+        syslog::SyslogReader,
+        LogReader,
+        LogRecord,
+    },
+    state::Snapshot,
 };
 #[derive(Clone, Debug)]
 pub(crate) struct LyreTail {
     drain: Arc<RwLock<SimpleDrain>>,
+    groups: Arc<RwLock<GroupTracker>>,
+    metrics: Arc<Metrics>,
     pub args: Arc<Mutex<Args>>,
 }
 
@@ -34,14 +57,20 @@ impl LyreTail {
         drain: Option<Arc<RwLock<SimpleDrain>>>,
         args: Arc<Mutex<Args>>,
     ) -> Result<Self, Error> {
+        let queue_size = args.lock().queue_size;
+        let anomaly_config = AnomalyConfig::from_args(&args.lock());
+        let drain = match drain {
+            Some(drain) => drain,
+            None => {
+                Arc::new(RwLock::new(SimpleDrain::new(masks::patterns(
+                    &args.lock(),
+                )?)?))
+            },
+        };
         Ok(Self {
-            drain: drain
-                .or_else(|| {
-                    Some(Arc::new(RwLock::new(
-                        SimpleDrain::new(vec![]).expect("creating new drain should work"),
-                    )))
-                })
-                .unwrap(),
+            drain,
+            groups: Arc::new(RwLock::new(GroupTracker::new(anomaly_config))),
+            metrics: Arc::new(Metrics::new(queue_size)),
             args,
         })
     }
@@ -50,22 +79,134 @@ impl LyreTail {
         self.drain.clone()
     }
 
+    pub(crate) fn get_groups_ref(&self) -> Arc<RwLock<GroupTracker>> {
+        self.groups.clone()
+    }
+
+    pub(crate) fn get_metrics_ref(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Summaries of every group seen so far, most frequently seen first
+    pub(crate) fn summaries(&self) -> Vec<GroupSummary> {
+        let drain = self.drain.read();
+        self.groups.read().summaries(&drain)
+    }
+
+    /// Replay a saved snapshot into the drain so groups keep their ids and counts across runs
+    #[instrument(level = "trace", skip(self))]
+    pub(crate) fn load_state(&self, path: &Path) -> Result<(), Error> {
+        let snapshot = Snapshot::load(path)?;
+        let mut drain = self.drain.write();
+        let mut groups = self.groups.write();
+        for saved in &snapshot.groups {
+            drain.process_line(saved.template.clone())?;
+            if let Some(key) = groups.observe(&drain, saved.last_seen, Side::Target) {
+                groups.restore(&key, saved);
+            }
+        }
+        groups.mark_baseline();
+        info!(groups = snapshot.groups.len(), "restored state");
+        Ok(())
+    }
+
+    /// Cluster the baseline snapshot and files, when any were given, ahead of the input so every
+    /// group knows how often it appeared in the baseline
+    #[instrument(level = "trace", skip(self))]
+    pub(crate) async fn load_baseline(&self) -> Result<(), Error> {
+        let (files, state, change_factor) = {
+            let args = self.args.lock();
+            (
+                args.baseline.clone(),
+                args.baseline_state.clone(),
+                args.change_factor,
+            )
+        };
+        if files.is_empty() && state.is_none() {
+            return Ok(());
+        }
+        if let Some(path) = state {
+            let snapshot = Snapshot::load(&path)?;
+            let mut drain = self.drain.write();
+            let mut groups = self.groups.write();
+            for saved in &snapshot.groups {
+                drain.process_line(saved.template.clone())?;
+                if let Some(key) = groups.observe(&drain, saved.last_seen, Side::Baseline) {
+                    groups.restore_baseline(&key, saved);
+                }
+            }
+            info!(groups = snapshot.groups.len(), "loaded baseline state");
+        }
+        if !files.is_empty() {
+            let (writer, reader) = record_channel(self.get_metrics_ref());
+            let reading = task::spawn(async move {
+                let reader = FileReader::new(files, false);
+                reader.read_logs(writer).await
+            });
+            self.spawn_processing(reader, Side::Baseline).await??;
+            reading.await??;
+            info!("loaded baseline files");
+        }
+        self.groups.write().compare_with_baseline(change_factor);
+        Ok(())
+    }
+
+    #[instrument(level = "trace", skip(self))]
+    pub(crate) fn save_state(&self, path: &Path) -> Result<(), Error> {
+        Snapshot::new(&self.summaries()).save(path)
+    }
+
     // init_input sets up the async background tasks which read and process lines from the source
-    //
+    // the returned handle completes once every source is exhausted and all lines are processed,
+    // with the error of the first source which failed
     #[instrument(level = "trace", skip_all)]
-    pub(crate) async fn init_input(&self) {
-        let drain = self.get_drain_ref();
-
+    pub(crate) fn init_input(&self) -> JoinHandle<Result<(), anyhow::Error>> {
         let follow = self.args.lock().follow;
-        let (writer, reader) = mpsc::unbounded_channel::<String>();
+        let (writer, reader) = record_channel(self.get_metrics_ref());
         let source_type = self.args.lock().source_type;
+        let mut readers: Vec<JoinHandle<Result<(), anyhow::Error>>> = vec![];
         match source_type {
             crate::sources::SourceType::File => {
-                let file = self.args.lock().file.clone().unwrap();
-                task::spawn(async move {
-                    let reader = FileReader::new(&file, follow);
-                    reader.read_logs(writer).await.unwrap();
-                });
+                let (stdin, files): (Vec<_>, Vec<_>) = self
+                    .args
+                    .lock()
+                    .file
+                    .iter()
+                    .cloned()
+                    .partition(|f| f == Path::new("-"));
+                if !stdin.is_empty() || files.is_empty() {
+                    let writer = writer.clone();
+                    readers.push(task::spawn(async move {
+                        let reader = StdinReader::new();
+                        reader.read_logs(writer).await
+                    }));
+                }
+                if !files.is_empty() {
+                    readers.push(task::spawn(async move {
+                        let reader = FileReader::new(files, follow);
+                        reader.read_logs(writer).await
+                    }));
+                }
+            },
+            crate::sources::SourceType::Syslog => {
+                let (udp, tcp) = {
+                    let args = self.args.lock();
+                    (args.syslog_udp, args.syslog_tcp)
+                };
+                readers.push(task::spawn(async move {
+                    let reader = SyslogReader::new(udp, tcp);
+                    reader.read_logs(writer).await
+                }));
+            },
+            crate::sources::SourceType::Socket => {
+                let (tcp, unix) = {
+                    let args = self.args.lock();
+                    (args.listen_tcp, args.listen_unix.clone())
+                };
+                readers.push(task::spawn(async move {
+                    let reader = SocketReader::new(tcp, unix);
+                    reader.read_logs(writer).await
+                }));
             },
             #[cfg(feature = "aws")]
             crate::sources::SourceType::Cloudwatch => {
@@ -75,32 +216,112 @@ impl LyreTail {
                 let since = args.since.clone();
                 let until = args.until.clone();
                 let window = args.window.clone();
-                task::spawn(async move {
+                readers.push(task::spawn(async move {
                     let reader = aws::cloudwatch::CloudwatchReader::new(
                         since, until, window, log_stream, log_group,
                     )
                     .await;
-                    reader.read_logs(writer).await.unwrap();
-                });
+                    reader.read_logs(writer).await
+                }));
+            },
+        };
+
+        let processing = self.spawn_processing(reader, Side::Target);
+        // Processing only finishes once every reader has dropped its writer, so by then their
+        // results are ready too
+        task::spawn(async move {
+            processing.await??;
+            for reader in readers {
+                reader.await??;
+            }
+            Ok(())
+        })
+    }
+
+    // spawn_processing runs records through multiline joining, parsing and filtering as configured
+    // before clustering them, the returned handle completes once the reader is exhausted and every
+    // step has finished, with the error of the first step which failed
+    fn spawn_processing(
+        &self,
+        reader: mpsc::Receiver<LogRecord>,
+        side: Side,
+    ) -> JoinHandle<Result<(), anyhow::Error>> {
+        let drain = self.get_drain_ref();
+        let groups = self.get_groups_ref();
+        let metrics = self.get_metrics_ref();
+        let batch_size = self.args.lock().batch_size;
+
+        let mut steps: Vec<JoinHandle<Result<(), anyhow::Error>>> = vec![];
+        let multiline = MultilineConfig::from_args(&self.args.lock());
+        let reader = match multiline {
+            Some(config) => {
+                let (tx, rx) = mpsc::channel(metrics.queue_capacity());
+                let combiner = Combiner::new(config, metrics.clone());
+                steps.push(task::spawn(combine(combiner, reader, tx)));
+                rx
             },
+            None => reader,
+        };
+        let mut stages: Vec<Box<dyn Stage>> = vec![];
+        if let Some(parser) = Parser::from_args(&self.args.lock()) {
+            stages.push(Box::new(parser));
+        }
+        if let Some(filter) = Filter::from_args(&self.args.lock(), metrics.clone()) {
+            stages.push(Box::new(filter));
+        }
+        let reader = if stages.is_empty() {
+            reader
+        } else {
+            let (tx, rx) = mpsc::channel(metrics.queue_capacity());
+            steps.push(task::spawn(transform(stages, reader, tx)));
+            rx
         };
 
-        task::spawn(async move { process_lines(drain, reader).await });
+        task::spawn(async move {
+            // A step which fails closes its output, so clustering finishes first either way
+            process_lines(drain, groups, metrics, batch_size, side, reader).await?;
+            for step in steps {
+                step.await??;
+            }
+            Ok(())
+        })
     }
 }
 
 #[instrument(skip_all, level = "trace")]
 async fn process_lines(
     drain: Arc<RwLock<SimpleDrain>>,
-    mut drain_reader: mpsc::UnboundedReceiver<String>,
+    groups: Arc<RwLock<GroupTracker>>,
+    metrics: Arc<Metrics>,
+    batch_size: usize,
+    side: Side,
+    mut drain_reader: mpsc::Receiver<LogRecord>,
 ) -> Result<(), anyhow::Error> {
-    loop {
-        tokio::select! {
-            maybe_line = drain_reader.recv() => {
-                if let Some(line) = maybe_line {
-                    drain.write().process_line(line)?;
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some(record) = drain_reader.recv().await {
+        batch.push(record);
+        // Take whatever else is already queued so the locks are acquired once per batch
+        while batch.len() < batch_size {
+            match drain_reader.try_recv() {
+                Ok(record) => batch.push(record),
+                Err(_) => break,
+            }
+        }
+        let processed = batch.len() as u64;
+        {
+            let mut drain = drain.write();
+            let mut groups = groups.write();
+            for record in batch.drain(..) {
+                drain.process_line(record.line.clone())?;
+                // Baseline lines only count towards the comparison, they don't feed rates or samples
+                match groups.observe(&drain, record.timestamp(), side) {
+                    Some(key) if side == Side::Target => groups.attribute(&key, &record),
+                    _ => {},
                 }
             }
         }
+        metrics.record_processed(processed);
     }
+    debug!("all sources closed, stopping processing");
+    Ok(())
 }
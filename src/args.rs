@@ -8,27 +8,136 @@
 // Server Side Public License along with this program.
 // If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
 
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
 use chrono::{DateTime, Duration, Utc};
 use clap::{ErrorKind, Parser};
 use dateparser::parse as dateparser;
 use duration_str::parse_chrono;
+use regex::Regex;
 use tracing::instrument;
 
-use crate::sources::SourceType;
+use crate::{
+    export::ExportFormat,
+    masks::Mask,
+    pipeline::{filter::Level, parse::ParseMode},
+    sources::SourceType,
+};
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, long_about = None)]
 pub(crate) struct Args {
     /// The type of source to read from
-    #[clap(arg_enum, long)]
+    #[clap(arg_enum, long, default_value = "file")]
     pub source_type: SourceType,
-    /// File path to read from, default to stdin if source_type is file
+    /// File paths or glob patterns to read from, may be repeated. Default to stdin if source_type is
+    /// file and none are given or the path is "-"
+    #[clap(long)]
+    pub file: Vec<PathBuf>,
+    /// Address to receive syslog messages on over UDP, such as 0.0.0.0:514, when source_type is
+    /// syslog
+    #[clap(long)]
+    pub syslog_udp: Option<SocketAddr>,
+    /// Address to accept syslog connections on over TCP, when source_type is syslog
+    #[clap(long)]
+    pub syslog_tcp: Option<SocketAddr>,
+    /// Address to accept connections sending newline delimited lines on, when source_type is
+    /// socket
     #[clap(long)]
-    pub file: Option<PathBuf>,
-    /// Whether to watch files for changes when the end is reached
+    pub listen_tcp: Option<SocketAddr>,
+    /// Path of a Unix socket to accept connections sending newline delimited lines on, when
+    /// source_type is socket
+    #[clap(long)]
+    pub listen_unix: Option<PathBuf>,
+    /// Keep reading as the file grows, reopening it if it is rotated or truncated like `tail -F`
     #[clap(long)]
     pub follow: bool,
+    /// Print summaries to stdout instead of running the interactive ui, implied when stdout is not
+    /// a terminal
+    #[clap(long)]
+    pub headless: bool,
+    /// Print a summary every interval while running headless, not just when input ends
+    #[clap(long)]
+    pub periodic: bool,
+    /// How often to print periodic summaries
+    #[clap(parse(try_from_str = parse_chrono), long, default_value = "10s")]
+    pub interval: Duration,
+    /// Maximum number of lines buffered between sources and processing, sources wait when it is full
+    #[clap(long, default_value = "10000")]
+    pub queue_size: usize,
+    /// Maximum number of queued lines processed each time the drain is locked
+    #[clap(long, default_value = "512")]
+    pub batch_size: usize,
+    /// Lines which don't match this pattern are joined onto the previous line, for stack traces
+    #[clap(long)]
+    pub multiline_start: Option<Regex>,
+    /// Lines starting with whitespace are joined onto the previous line
+    #[clap(long)]
+    pub multiline_indent: bool,
+    /// Most lines joined into a single multi-line record
+    #[clap(long, default_value = "500")]
+    pub multiline_max_lines: usize,
+    /// How long to wait for more continuation lines before passing a multi-line record on
+    #[clap(parse(try_from_str = parse_chrono), long, default_value = "500ms")]
+    pub multiline_timeout: Duration,
+    /// Format of each line, structured formats cluster on the message field and keep the rest as
+    /// attributes
+    #[clap(arg_enum, long, default_value = "plain")]
+    pub parser: ParseMode,
+    /// Field holding the message to cluster on, by default "message" for json and "msg" for logfmt.
+    /// Nested json fields use dotted paths
+    #[clap(long)]
+    pub message_field: Option<String>,
+    /// Only cluster lines matching one of these patterns, may be repeated
+    #[clap(long)]
+    pub include: Vec<Regex>,
+    /// Drop lines matching any of these patterns, may be repeated
+    #[clap(long)]
+    pub exclude: Vec<Regex>,
+    /// Drop parsed lines whose level field is below this, lines without a level are kept
+    #[clap(arg_enum, long)]
+    pub min_level: Option<Level>,
+    /// Built in masks for variable values to apply before clustering, may be repeated
+    #[clap(arg_enum, long)]
+    pub mask: Vec<Mask>,
+    /// Additional regular expression for variable values to mask before clustering, may be repeated
+    #[clap(long)]
+    pub mask_regex: Vec<Regex>,
+    /// TOML file with further settings, such as a [masks] table listing builtin masks and regex
+    /// patterns
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+    /// File to restore drain state from at startup and save it to on exit. Groups it doesn't contain
+    /// are flagged as new templates straight away
+    #[clap(long)]
+    pub state_file: Option<PathBuf>,
+    /// Groups which appear after this long are flagged as new templates, and groups younger than
+    /// this aren't checked for rate spikes
+    #[clap(parse(try_from_str = parse_chrono), long, default_value = "60s")]
+    pub warmup: Duration,
+    /// Flag a group as spiking when its rate over the last minute reaches this many times its
+    /// fifteen minute moving average
+    #[clap(long, default_value = "3.0")]
+    pub spike_factor: f64,
+    /// Lowest rate over the last minute, in lines per second, which can count as a spike
+    #[clap(long, default_value = "1.0")]
+    pub spike_min_rate: f64,
+    /// Files or glob patterns of baseline logs to compare the input against, may be repeated. They
+    /// are read in full before the input
+    #[clap(long)]
+    pub baseline: Vec<PathBuf>,
+    /// State file saved by an earlier run to compare the input against, with or without --baseline
+    #[clap(long)]
+    pub baseline_state: Option<PathBuf>,
+    /// Flag a group as changed from the baseline when its share of all lines grew or shrank by at
+    /// least this many times
+    #[clap(long, default_value = "2.0")]
+    pub change_factor: f64,
+    /// Machine readable format for summaries, used by headless output and the export key in the ui
+    #[clap(arg_enum, long)]
+    pub export_format: Option<ExportFormat>,
+    /// Where to write exports, stdout when "-" or when running headless without a path
+    #[clap(long)]
+    pub export_file: Option<PathBuf>,
     /// Cloudwatch Log Group to use
     #[cfg(feature = "aws")]
     #[clap(long)]
@@ -54,8 +163,30 @@ impl Args {
     /// Run complex validation on arguments
     #[instrument(level = "trace")]
     pub fn validate(&self) -> Result<(), clap::ErrorKind> {
+        if self.interval <= Duration::zero() || self.queue_size == 0 || self.batch_size == 0 {
+            return Err(ErrorKind::InvalidValue);
+        }
+        if self.multiline_timeout <= Duration::zero() || self.multiline_max_lines == 0 {
+            return Err(ErrorKind::InvalidValue);
+        }
+        if self.warmup < Duration::zero() || self.spike_factor <= 0.0 || self.spike_min_rate < 0.0 {
+            return Err(ErrorKind::InvalidValue);
+        }
+        if self.change_factor <= 1.0 {
+            return Err(ErrorKind::InvalidValue);
+        }
         match self.source_type {
             SourceType::File => {},
+            SourceType::Syslog => {
+                if self.syslog_udp.is_none() && self.syslog_tcp.is_none() {
+                    return Err(ErrorKind::MissingRequiredArgument);
+                }
+            },
+            SourceType::Socket => {
+                if self.listen_tcp.is_none() && self.listen_unix.is_none() {
+                    return Err(ErrorKind::MissingRequiredArgument);
+                }
+            },
             #[cfg(feature = "aws")]
             SourceType::Cloudwatch => {
                 if self.window.is_some() && (self.since.is_some() || self.until.is_some()) {
@@ -0,0 +1,112 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// Which logs a line belongs to when comparing against a baseline
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Side {
+    Baseline,
+    Target,
+}
+
+/// How a group's share of the lines differs between the baseline and the input
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Change {
+    /// Only seen in the input
+    Added,
+    /// Only seen in the baseline
+    Removed,
+    Increased,
+    Decreased,
+    Unchanged,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let change = match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Increased => "increased",
+            Change::Decreased => "decreased",
+            Change::Unchanged => "unchanged",
+        };
+        f.write_str(change)
+    }
+}
+
+/// A group's frequency in the baseline against the input
+///
+/// Frequencies are compared as shares of all lines on each side so that a baseline covering a
+/// longer or busier period doesn't make everything look like it decreased.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct Comparison {
+    pub baseline_count: usize,
+    /// Fraction of the baseline's lines in this group
+    pub baseline_share: f64,
+    /// Fraction of the input's lines in this group
+    pub share: f64,
+    pub change: Change,
+}
+
+impl Comparison {
+    /// Compare a group's counts, it has changed when its share moved by at least `factor` times
+    pub(crate) fn new(
+        count: usize,
+        total: usize,
+        baseline_count: usize,
+        baseline_total: usize,
+        factor: f64,
+    ) -> Self {
+        let share = count as f64 / total.max(1) as f64;
+        let baseline_share = baseline_count as f64 / baseline_total.max(1) as f64;
+        let change = if baseline_count == 0 {
+            Change::Added
+        } else if count == 0 {
+            Change::Removed
+        } else if share >= baseline_share * factor {
+            Change::Increased
+        } else if share * factor <= baseline_share {
+            Change::Decreased
+        } else {
+            Change::Unchanged
+        };
+        Self {
+            baseline_count,
+            baseline_share,
+            share,
+            change,
+        }
+    }
+
+    /// How many times larger the group's share of the input is than of the baseline
+    pub(crate) fn ratio(&self) -> f64 {
+        self.share / self.baseline_share
+    }
+
+    /// How far the group moved, groups on only one side first then by the size of the change
+    pub(crate) fn significance(&self) -> f64 {
+        match self.change {
+            Change::Added | Change::Removed => f64::INFINITY,
+            _ => self.ratio().ln().abs(),
+        }
+    }
+
+    /// Short description for tables, the change and how large it was
+    pub(crate) fn describe(&self) -> String {
+        match self.change {
+            Change::Added | Change::Removed => self.change.to_string(),
+            _ => format!("x{:.2}", self.ratio()),
+        }
+    }
+}
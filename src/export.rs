@@ -0,0 +1,294 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use clap::ArgEnum;
+use itertools::Itertools;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::{compare::Comparison, groups::GroupSummary, params::ParamSummary};
+
+/// Supported machine readable output formats
+#[derive(ArgEnum, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ExportFormat {
+    Json,
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// File written to when exporting without an explicit destination
+    pub(crate) fn default_path(&self) -> PathBuf {
+        PathBuf::from(format!("lyretail-export.{}", self.extension()))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ExportRecord<'a> {
+    uid: &'a str,
+    template: &'a str,
+    count: usize,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    parameters: &'a [ParamSummary],
+    /// Present when comparing against a baseline
+    #[serde(flatten)]
+    comparison: Option<Comparison>,
+}
+
+impl<'a> From<&'a GroupSummary> for ExportRecord<'a> {
+    fn from(group: &'a GroupSummary) -> Self {
+        Self {
+            uid: &group.id,
+            template: &group.template,
+            count: group.count,
+            first_seen: group.first_seen,
+            last_seen: group.last_seen,
+            parameters: &group.parameters,
+            comparison: group.comparison,
+        }
+    }
+}
+
+/// Write `groups` to `path`, or stdout when no path or "-" is given
+#[instrument(level = "trace", skip(groups))]
+pub(crate) fn export_groups(
+    format: ExportFormat,
+    groups: &[GroupSummary],
+    path: Option<&Path>,
+) -> Result<(), Error> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            let mut out = BufWriter::new(File::create(path)?);
+            write_groups(format, groups, &mut out)?;
+            out.flush()?;
+        },
+        _ => {
+            let mut out = stdout().lock();
+            write_groups(format, groups, &mut out)?;
+            out.flush()?;
+        },
+    }
+    Ok(())
+}
+
+pub(crate) fn write_groups<W: Write>(
+    format: ExportFormat,
+    groups: &[GroupSummary],
+    out: &mut W,
+) -> Result<(), Error> {
+    let records = groups.iter().map(ExportRecord::from);
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &records.collect::<Vec<_>>())?;
+            writeln!(out)?;
+        },
+        ExportFormat::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut *out, &record)?;
+                writeln!(out)?;
+            }
+        },
+        ExportFormat::Csv => {
+            // Comparison columns are only written when comparing against a baseline
+            let comparing = groups.iter().any(|group| group.comparison.is_some());
+            write!(out, "uid,template,count,first_seen,last_seen,parameters")?;
+            if comparing {
+                write!(out, ",baseline_count,baseline_share,share,change")?;
+            }
+            writeln!(out)?;
+            for record in records {
+                write!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    csv_field(record.uid),
+                    csv_field(record.template),
+                    record.count,
+                    record.first_seen.to_rfc3339(),
+                    record.last_seen.to_rfc3339(),
+                    csv_field(&csv_parameters(record.parameters)),
+                )?;
+                if let Some(comparison) = record.comparison {
+                    write!(
+                        out,
+                        ",{},{},{},{}",
+                        comparison.baseline_count,
+                        comparison.baseline_share,
+                        comparison.share,
+                        comparison.change,
+                    )?;
+                }
+                writeln!(out)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Flatten parameter statistics into one field, as `position:value=count value=count;...`
+fn csv_parameters(parameters: &[ParamSummary]) -> String {
+    parameters
+        .iter()
+        .map(|param| {
+            let values = param
+                .values
+                .iter()
+                .map(|v| format!("{}={}", v.value, v.count))
+                .join(" ");
+            format!("{}:{}", param.position, values)
+        })
+        .join(";")
+}
+
+/// Quote a field per RFC 4180 when it contains separators, quotes or line breaks
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::ValueCount;
+
+    fn group(id: &str, template: &str, comparison: Option<Comparison>) -> GroupSummary {
+        let seen = DateTime::parse_from_rfc3339("2022-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        GroupSummary {
+            key: id.to_string(),
+            id: id.to_string(),
+            template: template.to_string(),
+            count: 3,
+            first_seen: seen,
+            last_seen: seen,
+            rate: 0.0,
+            activity: vec![],
+            parameters: vec![ParamSummary {
+                position: 1,
+                values: vec![
+                    ValueCount {
+                        value: "a".to_string(),
+                        count: 2,
+                    },
+                    ValueCount {
+                        value: "b".to_string(),
+                        count: 1,
+                    },
+                ],
+            }],
+            anomaly: None,
+            comparison,
+        }
+    }
+
+    fn write(format: ExportFormat, groups: &[GroupSummary]) -> String {
+        let mut out = vec![];
+        write_groups(format, groups, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        assert_eq!(csv_field("user <*> logged in"), "user <*> logged in");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn fields_with_separators_are_quoted() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+        assert_eq!(csv_field("a\r\nb"), "\"a\r\nb\"");
+    }
+
+    #[test]
+    fn quotes_are_doubled() {
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("\""), "\"\"\"\"");
+    }
+
+    #[test]
+    fn csv_without_baseline() {
+        let out = write(ExportFormat::Csv, &[group("1", "user <*>, done", None)]);
+        assert_eq!(
+            out,
+            "uid,template,count,first_seen,last_seen,parameters\n\
+             1,\"user <*>, done\",3,2022-05-01T12:00:00+00:00,2022-05-01T12:00:00+00:00,1:a=2 b=1\n"
+        );
+    }
+
+    #[test]
+    fn csv_with_baseline_adds_comparison_columns() {
+        let comparison = Comparison::new(3, 3, 0, 10, 2.0);
+        let out = write(ExportFormat::Csv, &[group("1", "start", Some(comparison))]);
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "uid,template,count,first_seen,last_seen,parameters,\
+                 baseline_count,baseline_share,share,change"
+            )
+        );
+        assert!(lines.next().unwrap().ends_with(",1:a=2 b=1,0,0,1,added"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn json_is_an_array_of_groups() {
+        let groups = [group("1", "start", None), group("2", "stop", None)];
+        let out: serde_json::Value =
+            serde_json::from_str(&write(ExportFormat::Json, &groups)).unwrap();
+        let records = out.as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["uid"], "1");
+        assert_eq!(records[1]["template"], "stop");
+        assert_eq!(records[0]["count"], 3);
+        assert_eq!(records[0]["parameters"][0]["values"][1]["value"], "b");
+        assert!(records[0].get("change").is_none());
+    }
+
+    #[test]
+    fn ndjson_is_one_group_per_line() {
+        let comparison = Comparison::new(3, 3, 0, 10, 2.0);
+        let groups = [
+            group("1", "start", Some(comparison)),
+            group("2", "stop", None),
+        ];
+        let out = write(ExportFormat::Ndjson, &groups);
+        let records = out
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["change"], "added");
+        assert_eq!(records[0]["baseline_count"], 0);
+        assert_eq!(records[1]["uid"], "2");
+        assert!(records[1].get("change").is_none());
+    }
+}
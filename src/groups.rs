@@ -0,0 +1,549 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use drain_flow::{log_group::LogGroup, SimpleDrain};
+use itertools::Itertools;
+use tracing::{debug, instrument, trace};
+
+use crate::{
+    anomaly::{Anomaly, AnomalyConfig, AnomalyKind},
+    compare::{Change, Comparison, Side},
+    params::{ParamStats, ParamSummary},
+    sources::LogRecord,
+    state::SnapshotGroup,
+    timeline::{DecayingRate, Timeline},
+};
+
+/// Token drain uses in templates for positions which vary between lines
+pub(crate) const WILDCARD: &str = "<*>";
+// Number of recent lines kept for each group
+const MAX_SAMPLES: usize = 100;
+// Time constants of the decaying rate estimates, roughly the windows they average over
+const RATE_WINDOW_SECS: f64 = 60.0;
+const AVERAGE_WINDOW_SECS: f64 = 15.0 * 60.0;
+// Anomalies kept for headless mode to report, the oldest are dropped when nobody collects them
+const MAX_ANOMALIES: usize = 1000;
+// Seconds of recent activity included in summaries
+const ACTIVITY_SECS: usize = 60;
+
+/// Key used to look up lyretail's bookkeeping for a drain group
+pub(crate) fn group_key(lg: &LogGroup) -> String {
+    lg.event().uid.serialize().to_string()
+}
+
+/// Current copy of the drain group with the given key
+pub(crate) fn find_group(drain: &SimpleDrain, key: &str) -> Option<LogGroup> {
+    drain
+        .iter_groups()
+        .iter()
+        .flatten()
+        .find(|lg| group_key(lg) == key)
+        .copied()
+        .cloned()
+}
+
+/// A line which matched a group
+#[derive(Clone, Debug)]
+pub(crate) struct Sample {
+    pub at: DateTime<Utc>,
+    pub source: Arc<str>,
+    pub line: String,
+}
+
+/// Bookkeeping lyretail keeps alongside each drain group
+#[derive(Clone, Debug)]
+pub(crate) struct GroupInfo {
+    pub id: String,
+    pub count: usize,
+    /// Lines matched from the baseline being compared against
+    pub baseline_count: usize,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Lines matched from each source
+    pub sources: BTreeMap<Arc<str>, usize>,
+    /// Source and offset of the most recent line matched
+    pub last_location: Option<(Arc<str>, u64)>,
+    /// Structured fields of the most recent line matched
+    pub last_fields: BTreeMap<String, String>,
+    /// Most recent lines matched, newest first
+    pub samples: VecDeque<Sample>,
+    /// Values seen in each wildcard of the template
+    pub params: ParamStats,
+    /// Lines matched over time, by when they were read
+    pub timeline: Timeline,
+    /// Whether the group appeared after the warmup or wasn't in a loaded baseline
+    pub is_new: bool,
+    // Template as of the most recent line matched
+    template: String,
+    // When lyretail created the group, by its own clock rather than event time
+    created_at: DateTime<Utc>,
+    recent: DecayingRate,
+    average: DecayingRate,
+    // Whether the group was spiking as of its most recent line, so each spike is reported once
+    spiking: bool,
+    // Size of the drain group when it was last observed, used to spot which group a line joined
+    drain_len: usize,
+}
+
+impl GroupInfo {
+    fn new(id: String, template: String, drain_len: usize, at: DateTime<Utc>, side: Side) -> Self {
+        let now = Utc::now();
+        let (count, baseline_count) = match side {
+            Side::Target => (1, 0),
+            Side::Baseline => (0, 1),
+        };
+        Self {
+            id,
+            count,
+            baseline_count,
+            first_seen: at,
+            last_seen: at,
+            sources: BTreeMap::new(),
+            last_location: None,
+            last_fields: BTreeMap::new(),
+            samples: VecDeque::new(),
+            params: ParamStats::default(),
+            timeline: Timeline::new(),
+            is_new: false,
+            template,
+            created_at: now,
+            recent: DecayingRate::new(RATE_WINDOW_SECS, now),
+            average: DecayingRate::new(AVERAGE_WINDOW_SECS, now),
+            spiking: false,
+            drain_len,
+        }
+    }
+
+    /// Lines per second over roughly the last minute
+    pub(crate) fn recent_rate(&self, now: DateTime<Utc>) -> f64 {
+        self.recent.at(now)
+    }
+
+    /// Lines per second over roughly the last fifteen minutes
+    pub(crate) fn moving_average(&self, now: DateTime<Utc>) -> f64 {
+        self.average.at(now)
+    }
+
+    /// Whether the recent rate is well above the moving average, once the group is old enough for
+    /// the average to mean something
+    pub(crate) fn is_spiking(&self, config: &AnomalyConfig, now: DateTime<Utc>) -> bool {
+        now - self.created_at >= config.warmup
+            && config.is_spike(self.recent_rate(now), self.moving_average(now))
+    }
+
+    /// The anomaly to highlight the group with, a spike is more pressing than being new
+    pub(crate) fn anomaly(
+        &self,
+        config: &AnomalyConfig,
+        now: DateTime<Utc>,
+    ) -> Option<AnomalyKind> {
+        if self.is_spiking(config, now) {
+            Some(AnomalyKind::RateSpike)
+        } else if self.is_new {
+            Some(AnomalyKind::NewTemplate)
+        } else {
+            None
+        }
+    }
+
+    fn anomaly_event(&self, kind: AnomalyKind, now: DateTime<Utc>) -> Anomaly {
+        Anomaly {
+            at: now,
+            kind,
+            uid: self.id.clone(),
+            template: self.template.trim_end().to_string(),
+            rate: self.recent_rate(now),
+            average: self.moving_average(now),
+        }
+    }
+
+    /// Lines per second between the first and last time the group was seen
+    pub(crate) fn average_rate(&self) -> f64 {
+        let secs = (self.last_seen - self.first_seen).num_milliseconds() as f64 / 1000.0;
+        if secs > 0.0 {
+            self.count as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Point in time view of a group combining the drain template with lyretail's bookkeeping
+#[derive(Clone, Debug)]
+pub(crate) struct GroupSummary {
+    pub key: String,
+    pub id: String,
+    pub template: String,
+    pub count: usize,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Lines per second over roughly the last minute
+    pub rate: f64,
+    /// Lines in each second of the last minute, oldest first
+    pub activity: Vec<u64>,
+    /// Most frequent values of each wildcard in the template
+    pub parameters: Vec<ParamSummary>,
+    pub anomaly: Option<AnomalyKind>,
+    /// How the group differs from the baseline, when comparing against one
+    pub comparison: Option<Comparison>,
+}
+
+impl GroupSummary {
+    /// Whether the group differs from the baseline being compared against
+    pub(crate) fn has_changed(&self) -> bool {
+        matches!(self.comparison, Some(c) if c.change != Change::Unchanged)
+    }
+}
+
+/// Orderings the group table can be sorted by
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SortOrder {
+    Count,
+    Rate,
+    FirstSeen,
+    LastSeen,
+    Template,
+    Change,
+}
+
+impl SortOrder {
+    /// The ordering after this one, wrapping around
+    pub(crate) fn next(self) -> Self {
+        match self {
+            SortOrder::Count => SortOrder::Rate,
+            SortOrder::Rate => SortOrder::FirstSeen,
+            SortOrder::FirstSeen => SortOrder::LastSeen,
+            SortOrder::LastSeen => SortOrder::Template,
+            SortOrder::Template => SortOrder::Change,
+            SortOrder::Change => SortOrder::Count,
+        }
+    }
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            SortOrder::Count => "count",
+            SortOrder::Rate => "recent rate",
+            SortOrder::FirstSeen => "first seen",
+            SortOrder::LastSeen => "last seen",
+            SortOrder::Template => "template",
+            SortOrder::Change => "change from baseline",
+        }
+    }
+
+    /// Compare two groups, the most interesting first. Ties are broken by key so groups don't
+    /// swap places between frames
+    pub(crate) fn compare(&self, a: &GroupSummary, b: &GroupSummary) -> Ordering {
+        let ordering = match self {
+            SortOrder::Count => Ord::cmp(&b.count, &a.count),
+            SortOrder::Rate => b.rate.partial_cmp(&a.rate).unwrap_or(Ordering::Equal),
+            SortOrder::FirstSeen => Ord::cmp(&b.first_seen, &a.first_seen),
+            SortOrder::LastSeen => Ord::cmp(&b.last_seen, &a.last_seen),
+            SortOrder::Template => Ord::cmp(&a.template, &b.template),
+            SortOrder::Change => {
+                let significance =
+                    |s: &GroupSummary| s.comparison.map_or(0.0, |c| c.significance());
+                significance(b)
+                    .partial_cmp(&significance(a))
+                    .unwrap_or(Ordering::Equal)
+            },
+        };
+        ordering.then_with(|| Ord::cmp(&a.key, &b.key))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct GroupTracker {
+    groups: HashMap<String, GroupInfo>,
+    anomaly_config: AnomalyConfig,
+    // Groups created from this time on are new templates
+    new_after: DateTime<Utc>,
+    anomalies: VecDeque<Anomaly>,
+    // Factor a group's share of the lines must change by to differ from the baseline, set when
+    // comparing against one
+    change_factor: Option<f64>,
+    // Size of each drain group by its position in `iter_groups` as of the last line observed
+    drain_lens: Vec<usize>,
+}
+
+impl GroupTracker {
+    pub(crate) fn new(anomaly_config: AnomalyConfig) -> Self {
+        Self {
+            groups: HashMap::new(),
+            new_after: Utc::now() + anomaly_config.warmup,
+            anomaly_config,
+            anomalies: VecDeque::new(),
+            change_factor: None,
+            drain_lens: vec![],
+        }
+    }
+
+    /// Compare the groups against the baseline lines observed so far from now on
+    pub(crate) fn compare_with_baseline(&mut self, change_factor: f64) {
+        self.change_factor = Some(change_factor);
+        self.mark_baseline();
+    }
+
+    pub(crate) fn is_comparing(&self) -> bool {
+        self.change_factor.is_some()
+    }
+
+    /// Treat the groups seen so far as a known baseline, skipping the warmup so any group created
+    /// from now on is new
+    pub(crate) fn mark_baseline(&mut self) {
+        self.new_after = Utc::now();
+    }
+
+    /// Anomalies detected since the last call, oldest first
+    pub(crate) fn take_anomalies(&mut self) -> Vec<Anomaly> {
+        self.anomalies.drain(..).collect()
+    }
+
+    fn report(&mut self, anomaly: Anomaly) {
+        debug!(?anomaly, "anomaly detected");
+        self.anomalies.push_back(anomaly);
+        if self.anomalies.len() > MAX_ANOMALIES {
+            self.anomalies.pop_front();
+        }
+    }
+
+    /// Attribute the line most recently given to `drain` to the group which changed size
+    ///
+    /// drain-flow doesn't report which group a line was sorted into, so this compares every group
+    /// against the size it had last time and returns the key of the one which grew or appeared.
+    /// Lines from a baseline are only counted, they are never new templates.
+    #[instrument(level = "trace", skip_all)]
+    pub(crate) fn observe(
+        &mut self,
+        drain: &SimpleDrain,
+        at: DateTime<Utc>,
+        side: Side,
+    ) -> Option<String> {
+        let (lg, key) = self.touched_group(drain)?;
+        let len = lg.len();
+        match self.groups.get_mut(&key) {
+            Some(info) => {
+                info.drain_len = len;
+                match side {
+                    Side::Target => {
+                        // A group the baseline created is only first seen once the input has it
+                        if info.count == 0 {
+                            info.first_seen = at;
+                        }
+                        info.count += 1;
+                        info.last_seen = at;
+                    },
+                    Side::Baseline => info.baseline_count += 1,
+                }
+                info.template = lg.event().to_string();
+            },
+            None => {
+                trace!(%key, "new group");
+                let mut info = GroupInfo::new(key.clone(), lg.event().to_string(), len, at, side);
+                if side == Side::Target && info.created_at >= self.new_after {
+                    info.is_new = true;
+                    self.report(info.anomaly_event(AnomalyKind::NewTemplate, info.created_at));
+                }
+                self.groups.insert(key.clone(), info);
+            },
+        }
+        Some(key)
+    }
+
+    /// Drain group which grew or appeared since the last call, along with its key
+    ///
+    /// Building a key allocates, so groups are first compared by size against `drain_lens` and
+    /// only those which differ are looked up. Positions shift as drain adds groups, so a
+    /// candidate is confirmed against the size recorded under its key, falling back to checking
+    /// every group when none is.
+    fn touched_group<'a>(&mut self, drain: &'a SimpleDrain) -> Option<(&'a LogGroup, String)> {
+        let groups = drain.iter_groups();
+        self.drain_lens.resize(groups.iter().map(Vec::len).sum(), 0);
+        for (lg, seen_len) in groups.iter().flatten().zip(self.drain_lens.iter_mut()) {
+            let len = lg.len();
+            if *seen_len == len {
+                continue;
+            }
+            *seen_len = len;
+            let key = group_key(lg);
+            match self.groups.get(&key) {
+                Some(info) if info.drain_len == len => {},
+                _ => return Some((lg, key)),
+            }
+        }
+        groups.iter().flatten().find_map(|lg| {
+            let key = group_key(lg);
+            match self.groups.get(&key) {
+                Some(info) if info.drain_len == lg.len() => None,
+                _ => Some((*lg, key)),
+            }
+        })
+    }
+
+    /// Record where the line `observe` attributed to `key` came from
+    pub(crate) fn attribute(&mut self, key: &str, record: &LogRecord) {
+        let mut spike = None;
+        if let Some(info) = self.groups.get_mut(key) {
+            *info.sources.entry(record.source.clone()).or_default() += 1;
+            info.last_location = Some((record.source.clone(), record.offset));
+            info.last_fields = record.fields.clone();
+            info.recent.record(record.ingested_at);
+            info.average.record(record.ingested_at);
+            let spiking = info.is_spiking(&self.anomaly_config, record.ingested_at);
+            if spiking && !info.spiking {
+                spike = Some(info.anomaly_event(AnomalyKind::RateSpike, record.ingested_at));
+            }
+            info.spiking = spiking;
+            info.timeline.record(record.ingested_at);
+            info.params.record(
+                &info.template,
+                &record.line,
+                info.samples.iter().map(|sample| sample.line.as_str()),
+            );
+            info.samples.push_front(Sample {
+                at: record.timestamp(),
+                source: record.source.clone(),
+                line: record.line.clone(),
+            });
+            info.samples.truncate(MAX_SAMPLES);
+        }
+        if let Some(spike) = spike {
+            self.report(spike);
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&GroupInfo> {
+        self.groups.get(key)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub(crate) fn anomaly_config(&self) -> &AnomalyConfig {
+        &self.anomaly_config
+    }
+
+    /// Carry a saved group's id and history over to the group its template was replayed into
+    ///
+    /// The replayed template was already counted once by `observe`. Several saved templates can
+    /// land in the same group, in which case the first id is kept and the counts are combined.
+    pub(crate) fn restore(&mut self, key: &str, saved: &SnapshotGroup) {
+        if let Some(info) = self.groups.get_mut(key) {
+            if info.id == key {
+                info.id = saved.uid.clone();
+            }
+            info.count += saved.count.saturating_sub(1);
+            info.first_seen = info.first_seen.min(saved.first_seen);
+            info.last_seen = info.last_seen.max(saved.last_seen);
+        }
+    }
+
+    /// Count a saved baseline group towards the group its template was replayed into, keeping its
+    /// id unless the group already has one
+    ///
+    /// The replayed template was already counted once by `observe`.
+    pub(crate) fn restore_baseline(&mut self, key: &str, saved: &SnapshotGroup) {
+        if let Some(info) = self.groups.get_mut(key) {
+            if info.id == key {
+                info.id = saved.uid.clone();
+            }
+            info.baseline_count += saved.count.saturating_sub(1);
+        }
+    }
+
+    // Lines counted across every group, from the input and from the baseline
+    fn totals(&self) -> (usize, usize) {
+        self.groups.values().fold((0, 0), |(t, b), info| {
+            (t + info.count, b + info.baseline_count)
+        })
+    }
+
+    fn compare(
+        &self,
+        info: &GroupInfo,
+        (total, baseline_total): (usize, usize),
+    ) -> Option<Comparison> {
+        self.change_factor.map(|factor| {
+            Comparison::new(
+                info.count,
+                total,
+                info.baseline_count,
+                baseline_total,
+                factor,
+            )
+        })
+    }
+
+    /// How the group with `key` differs from the baseline, when comparing against one
+    pub(crate) fn comparison(&self, key: &str) -> Option<Comparison> {
+        let info = self.groups.get(key)?;
+        self.compare(info, self.totals())
+    }
+
+    /// Summaries of every group in `drain`, most frequently seen first
+    pub(crate) fn summaries(&self, drain: &SimpleDrain) -> Vec<GroupSummary> {
+        let totals = self.totals();
+        drain
+            .iter_groups()
+            .iter()
+            .flatten()
+            .map(|lg| self.summarize(lg, totals))
+            .sorted_by(|a, b| SortOrder::Count.compare(a, b))
+            .collect()
+    }
+
+    fn summarize(&self, lg: &LogGroup, totals: (usize, usize)) -> GroupSummary {
+        let key = group_key(lg);
+        let template = lg.event().to_string().trim_end().to_string();
+        match self.groups.get(&key) {
+            Some(info) => {
+                let parameters = info.params.summaries(&template);
+                let now = Utc::now();
+                let comparison = self.compare(info, totals);
+                GroupSummary {
+                    key,
+                    id: info.id.clone(),
+                    template,
+                    count: info.count,
+                    first_seen: info.first_seen,
+                    last_seen: info.last_seen,
+                    rate: info.recent_rate(now),
+                    activity: info.timeline.per_second(now, ACTIVITY_SECS),
+                    anomaly: info.anomaly(&self.anomaly_config, now),
+                    parameters,
+                    comparison,
+                }
+            },
+            None => {
+                let now = Utc::now();
+                GroupSummary {
+                    id: key.clone(),
+                    key,
+                    template,
+                    count: lg.len(),
+                    first_seen: now,
+                    last_seen: now,
+                    rate: 0.0,
+                    activity: vec![],
+                    parameters: vec![],
+                    anomaly: None,
+                    comparison: None,
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{
+    io::{stderr, stdout, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Error;
+use chrono::Utc;
+use tokio::{signal, task::JoinHandle, time};
+use tracing::{debug, instrument};
+
+use crate::{
+    anomaly::AnomalyKind,
+    app::LyreTail,
+    export::export_groups,
+    groups::{GroupSummary, SortOrder},
+};
+
+// How often newly detected anomalies are reported
+const ANOMALY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Non-interactive frontend which prints group summaries to stdout
+#[derive(Clone, Debug)]
+pub(crate) struct Headless {
+    app: Arc<LyreTail>,
+}
+
+impl Headless {
+    pub(crate) fn new(app: Arc<LyreTail>) -> Self {
+        Self { app }
+    }
+
+    /// Print summaries until processing finishes or ctrl-c is received, then print a final one
+    #[instrument(level = "trace", skip_all)]
+    pub(crate) async fn run(
+        &self,
+        processing: JoinHandle<Result<(), anyhow::Error>>,
+    ) -> Result<(), Error> {
+        let (periodic, interval) = {
+            let args = self.app.args.lock();
+            (args.periodic, args.interval.to_std()?)
+        };
+        let mut ticker = time::interval_at(time::Instant::now() + interval, interval);
+        let mut anomalies = time::interval(ANOMALY_INTERVAL);
+        let ctrl_c = signal::ctrl_c();
+        tokio::pin!(processing, ctrl_c);
+        // A processing error is only returned after the final summary so the work done is not lost
+        let mut result = Ok(());
+        loop {
+            tokio::select! {
+                res = &mut processing => {
+                    debug!("input finished");
+                    result = res.map_err(Error::from).and_then(|res| res);
+                    break;
+                }
+                _ = &mut ctrl_c => {
+                    debug!("ctrl-c received");
+                    break;
+                }
+                _ = ticker.tick(), if periodic => {
+                    self.print_summary("periodic")?;
+                }
+                _ = anomalies.tick() => {
+                    self.print_anomalies()?;
+                }
+            }
+        }
+        self.print_anomalies()?;
+        self.print_summary("final")?;
+        result
+    }
+
+    /// Report anomalies detected since the last call. With an export format they are written to
+    /// stderr as JSON lines so stdout stays machine readable
+    fn print_anomalies(&self) -> Result<(), Error> {
+        let anomalies = self.app.get_groups_ref().write().take_anomalies();
+        if anomalies.is_empty() {
+            return Ok(());
+        }
+        if self.app.args.lock().export_format.is_some() {
+            let mut out = stderr().lock();
+            for anomaly in anomalies {
+                serde_json::to_writer(&mut out, &anomaly)?;
+                writeln!(out)?;
+            }
+            return Ok(());
+        }
+        let mut out = stdout().lock();
+        for anomaly in anomalies {
+            match anomaly.kind {
+                AnomalyKind::NewTemplate => {
+                    writeln!(
+                        out,
+                        "!!! {} at {}, {}: {}",
+                        anomaly.kind,
+                        anomaly.at.to_rfc3339(),
+                        anomaly.uid,
+                        anomaly.template
+                    )?;
+                },
+                AnomalyKind::RateSpike => {
+                    writeln!(
+                        out,
+                        "!!! {} at {}, {} at {:.2}/s against a {:.2}/s average: {}",
+                        anomaly.kind,
+                        anomaly.at.to_rfc3339(),
+                        anomaly.uid,
+                        anomaly.rate,
+                        anomaly.average,
+                        anomaly.template
+                    )?;
+                },
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn print_summary(&self, kind: &str) -> Result<(), Error> {
+        let groups = self.app.summaries();
+        let (export_format, export_file) = {
+            let args = self.app.args.lock();
+            (args.export_format, args.export_file.clone())
+        };
+        if let Some(format) = export_format {
+            return export_groups(format, &groups, export_file.as_deref());
+        }
+        let mut out = stdout().lock();
+        let metrics = self.app.get_metrics_ref();
+        writeln!(
+            out,
+            "--- {} summary at {}, {} groups, {} lines processed, {} filtered, {} queued ---",
+            kind,
+            Utc::now().to_rfc3339(),
+            groups.len(),
+            metrics.processed(),
+            metrics.filtered(),
+            metrics.queue_depth()
+        )?;
+        if groups.iter().any(|group| group.comparison.is_some()) {
+            return Self::print_comparison(&mut out, groups);
+        }
+        writeln!(out, "{:<27} {:>10} Event", "ID", "Count")?;
+        for group in groups {
+            writeln!(
+                out,
+                "{:<27} {:>10} {}",
+                group.id, group.count, group.template
+            )?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Print groups against the baseline, those which changed the most first
+    fn print_comparison<W: Write>(out: &mut W, mut groups: Vec<GroupSummary>) -> Result<(), Error> {
+        groups.sort_by(|a, b| SortOrder::Change.compare(a, b));
+        writeln!(
+            out,
+            "{:<27} {:>10} {:>10} {:<10} Event",
+            "ID", "Count", "Baseline", "Change"
+        )?;
+        for group in groups {
+            if let Some(comparison) = group.comparison {
+                writeln!(
+                    out,
+                    "{:<27} {:>10} {:>10} {:<10} {}",
+                    group.id,
+                    group.count,
+                    comparison.baseline_count,
+                    comparison.describe(),
+                    group.template
+                )?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
@@ -11,16 +11,30 @@
 #![feature(associated_type_bounds)]
 extern crate enum_kinds;
 extern crate tracing;
+mod anomaly;
 mod app;
 mod args;
+mod compare;
+mod export;
+mod groups;
+mod headless;
+mod masks;
+mod metrics;
+mod params;
+mod pipeline;
 mod sources;
+mod state;
+mod timeline;
 mod ui;
 
-use std::{fs::File, sync::Arc};
+use std::{fs::File, io::stdout, process, sync::Arc};
 
 use app::LyreTail;
-use clap::{CommandFactory, Parser};
+use clap::{CommandFactory, ErrorKind, Parser};
+use crossterm::tty::IsTty;
 use drain_flow::SimpleDrain;
+use futures::FutureExt;
+use headless::Headless;
 use parking_lot::{Mutex, RwLock};
 use tracing::debug;
 use tracing_subscriber::{fmt::format::FmtSpan, prelude::*, EnvFilter};
@@ -57,15 +71,56 @@ async fn main() {
         },
     };
     debug!("validated args");
+    let headless = args_inner.headless || !stdout().is_tty();
+    let state_file = args_inner.state_file.clone();
+    let masks = masks::patterns(&args_inner).unwrap_or_else(|e| {
+        let mut cmd = Args::command();
+        cmd.error(ErrorKind::InvalidValue, e).exit();
+    });
     let args = Arc::new(Mutex::new(args_inner));
-    let drain = Arc::new(RwLock::new(SimpleDrain::new(vec![]).unwrap()));
+    let drain = Arc::new(RwLock::new(SimpleDrain::new(masks).unwrap()));
     debug!("got drain");
     let app = LyreTail::create_app(Some(drain), args).unwrap();
     debug!("got app");
+    if let Some(path) = state_file.as_deref().filter(|p| p.exists()) {
+        app.load_state(path).unwrap_or_else(|e| {
+            let mut cmd = Args::command();
+            cmd.error(
+                ErrorKind::InvalidValue,
+                format!("Could not load state file {}: {:#}", path.display(), e),
+            )
+            .exit();
+        });
+    }
+    app.load_baseline().await.unwrap_or_else(|e| {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::InvalidValue,
+            format!("Could not load the baseline: {:#}", e),
+        )
+        .exit();
+    });
     let app_ref = Arc::new(app);
-    app_ref.init_input().await;
+    let processing = app_ref.init_input();
     debug!("app running");
-    let mut ui = Ui::new(app_ref.clone()).unwrap();
-    debug!("got ui");
-    ui.run_ui().unwrap();
+    let failure = if headless {
+        Headless::new(app_ref.clone()).run(processing).await.err()
+    } else {
+        let mut ui = Ui::new(app_ref.clone()).unwrap();
+        debug!("got ui");
+        ui.run_ui().unwrap();
+        // A source which failed while the ui was open is reported once the terminal is restored
+        match processing.now_or_never() {
+            Some(Ok(res)) => res.err(),
+            Some(Err(e)) => Some(e.into()),
+            None => None,
+        }
+    };
+    if let Some(path) = state_file {
+        app_ref.save_state(&path).unwrap();
+    }
+    if let Some(e) = failure {
+        eprintln!("error: {:#}", e);
+        process::exit(1);
+    }
 }
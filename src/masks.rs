@@ -0,0 +1,213 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Error};
+use clap::ArgEnum;
+use itertools::Itertools;
+use regex::Regex;
+use serde::Deserialize;
+use tracing::{debug, instrument};
+
+use crate::args::Args;
+
+/// Built in patterns for values which vary between otherwise identical lines
+#[derive(ArgEnum, Deserialize, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Mask {
+    Ipv4,
+    Ipv6,
+    Uuid,
+    Hex,
+    Number,
+    Email,
+    Path,
+}
+
+impl Mask {
+    pub(crate) fn pattern(&self) -> &'static str {
+        match self {
+            Mask::Ipv4 => r"\b(?:\d{1,3}\.){3}\d{1,3}(?::\d{1,5})?\b",
+            Mask::Ipv6 => {
+                r"\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b|\b(?:[0-9A-Fa-f]{1,4}:){1,6}:(?:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,5})?|\B::[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6}\b"
+            },
+            Mask::Uuid => {
+                r"\b[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}\b"
+            },
+            Mask::Hex => r"\b(?:0[xX][0-9A-Fa-f]+|[0-9A-Fa-f]{8,})\b",
+            // Numbers along with a sign and common duration, size and percentage suffixes, which
+            // must end the word so the number in `5min` isn't masked as `5m` leaving `in`
+            Mask::Number => {
+                r"(?:\B-)?\b\d+(?:\.\d+)?(?:(?:ns|us|µs|ms|s|m|h|d|[KMGT]i?B|[kmgt]b)\b|%|\b)"
+            },
+            Mask::Email => r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b",
+            Mask::Path => r"(?:/[\w.-]+){2,}/?",
+        }
+    }
+}
+
+/// Settings read from the file given with `--config`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub masks: MaskConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MaskConfig {
+    /// Built in masks to enable
+    pub builtin: Vec<Mask>,
+    /// Additional regular expressions to mask
+    pub regex: Vec<String>,
+}
+
+impl Config {
+    #[instrument(level = "trace")]
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        for pattern in &config.masks.regex {
+            Regex::new(pattern)
+                .map_err(|e| anyhow!("invalid mask regex in {}: {}", path.display(), e))?;
+        }
+        Ok(config)
+    }
+}
+
+/// Masking patterns to build the drain with, from the command line and any config file
+#[instrument(level = "trace", skip(args))]
+pub(crate) fn patterns(args: &Args) -> Result<Vec<String>, Error> {
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let builtin = args
+        .mask
+        .iter()
+        .chain(config.masks.builtin.iter())
+        .sorted()
+        .dedup()
+        .map(|mask| mask.pattern().to_string());
+    let custom = args
+        .mask_regex
+        .iter()
+        .map(|re| re.as_str().to_string())
+        .chain(config.masks.regex.into_iter());
+    let patterns = builtin.chain(custom).collect::<Vec<_>>();
+    debug!(?patterns, "masking patterns");
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn mask(mask: Mask, line: &str) -> String {
+        Regex::new(mask.pattern())
+            .unwrap()
+            .replace_all(line, "<*>")
+            .into_owned()
+    }
+
+    #[test]
+    fn masks_ip_addresses() {
+        assert_eq!(mask(Mask::Ipv4, "from 10.0.0.1:5432 ok"), "from <*> ok");
+        assert_eq!(mask(Mask::Ipv4, "version 1.2.3"), "version 1.2.3");
+        assert_eq!(mask(Mask::Ipv6, "from fe80::1 ok"), "from <*> ok");
+        assert_eq!(mask(Mask::Ipv6, "from ::1 ok"), "from <*> ok");
+        assert_eq!(
+            mask(Mask::Ipv6, "from 2001:db8:0:0:0:0:0:1 ok"),
+            "from <*> ok"
+        );
+        assert_eq!(mask(Mask::Ipv6, "at 12:30 ok"), "at 12:30 ok");
+    }
+
+    #[test]
+    fn masks_ids() {
+        assert_eq!(
+            mask(Mask::Uuid, "job 123e4567-e89b-12d3-a456-426614174000 done"),
+            "job <*> done"
+        );
+        assert_eq!(
+            mask(Mask::Hex, "ptr 0x7ffd1c and deadbeef00"),
+            "ptr <*> and <*>"
+        );
+        assert_eq!(mask(Mask::Hex, "code cafe"), "code cafe");
+    }
+
+    #[test]
+    fn masks_numbers_with_suffixes() {
+        assert_eq!(
+            mask(Mask::Number, "took 15ms, 2.5s and 3m"),
+            "took <*>, <*> and <*>"
+        );
+        assert_eq!(
+            mask(Mask::Number, "used 512MiB 4GB 80%"),
+            "used <*> <*> <*>"
+        );
+        assert_eq!(mask(Mask::Number, "retry -1 of 3"), "retry <*> of <*>");
+        assert_eq!(mask(Mask::Number, "shard a-1"), "shard a-<*>");
+    }
+
+    #[test]
+    fn number_suffixes_end_the_word() {
+        assert_eq!(mask(Mask::Number, "after 5min"), "after 5min");
+        assert_eq!(mask(Mask::Number, "after 10secs"), "after 10secs");
+        assert_eq!(mask(Mask::Number, "host web01"), "host web01");
+    }
+
+    #[test]
+    fn masks_emails_and_paths() {
+        assert_eq!(
+            mask(Mask::Email, "sent to bob.smith@example.com"),
+            "sent to <*>"
+        );
+        assert_eq!(
+            mask(Mask::Path, "open /var/log/app.log failed"),
+            "open <*> failed"
+        );
+        assert_eq!(mask(Mask::Path, "open /tmp failed"), "open /tmp failed");
+    }
+
+    #[test]
+    fn patterns_are_ordered_and_deduplicated() {
+        let config =
+            std::env::temp_dir().join(format!("lyretail-masks-{}.toml", std::process::id()));
+        fs::write(
+            &config,
+            "[masks]\nbuiltin = [\"uuid\", \"number\"]\nregex = [\"req-[0-9]+\"]\n",
+        )
+        .unwrap();
+        let args = Args::parse_from([
+            "lyretail",
+            "--mask=number",
+            "--mask=ipv4",
+            "--mask=number",
+            "--mask-regex=user=\\w+",
+            "--config",
+            config.to_str().unwrap(),
+        ]);
+        let patterns = patterns(&args);
+        fs::remove_file(&config).unwrap();
+        assert_eq!(
+            patterns.unwrap(),
+            vec![
+                Mask::Ipv4.pattern().to_string(),
+                Mask::Uuid.pattern().to_string(),
+                Mask::Number.pattern().to_string(),
+                r"user=\w+".to_string(),
+                "req-[0-9]+".to_string(),
+            ]
+        );
+    }
+}
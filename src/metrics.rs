@@ -0,0 +1,135 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+/// What a source is doing, reported by readers for the status bar
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum SourceState {
+    /// Reading lines as fast as they can be queued
+    Reading,
+    /// Caught up with the end of the source and waiting for more
+    Following,
+    /// Waiting for connections or datagrams on a socket
+    Listening,
+    /// Fetching pages of events from CloudWatch
+    #[cfg(feature = "aws")]
+    Paging,
+    /// Finished, every line has been read
+    Eof,
+    /// Stopped by an error
+    Error,
+}
+
+impl fmt::Display for SourceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            SourceState::Reading => "reading",
+            SourceState::Following => "following",
+            SourceState::Listening => "listening",
+            #[cfg(feature = "aws")]
+            SourceState::Paging => "paging",
+            SourceState::Eof => "eof",
+            SourceState::Error => "error",
+        };
+        f.write_str(state)
+    }
+}
+
+/// Counters describing the ingest pipeline, shared between sources, processing and frontends
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    queue_capacity: usize,
+    queued: AtomicU64,
+    processed: AtomicU64,
+    combined: AtomicU64,
+    filtered: AtomicU64,
+    started: Instant,
+    sources: Mutex<BTreeMap<Arc<str>, SourceState>>,
+}
+
+impl Metrics {
+    pub(crate) fn new(queue_capacity: usize) -> Self {
+        Self {
+            queue_capacity,
+            queued: AtomicU64::new(0),
+            processed: AtomicU64::new(0),
+            combined: AtomicU64::new(0),
+            filtered: AtomicU64::new(0),
+            started: Instant::now(),
+            sources: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub(crate) fn record_queued(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_processed(&self, lines: u64) {
+        self.processed.fetch_add(lines, Ordering::Relaxed);
+    }
+
+    /// Count lines which were folded into an earlier multi-line record
+    pub(crate) fn record_combined(&self, lines: u64) {
+        self.combined.fetch_add(lines, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_filtered(&self) {
+        self.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// Lines dropped by include, exclude or level filters
+    pub(crate) fn filtered(&self) -> u64 {
+        self.filtered.load(Ordering::Relaxed)
+    }
+
+    /// Lines sent by sources which haven't been through the drain yet
+    pub(crate) fn queue_depth(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed).saturating_sub(
+            self.processed() + self.combined.load(Ordering::Relaxed) + self.filtered(),
+        )
+    }
+
+    pub(crate) fn queue_capacity(&self) -> usize {
+        self.queue_capacity
+    }
+
+    pub(crate) fn set_source_state(&self, source: &Arc<str>, state: SourceState) {
+        self.sources.lock().insert(source.clone(), state);
+    }
+
+    /// Forget a source which has gone away for good, such as a closed connection
+    pub(crate) fn remove_source(&self, source: &Arc<str>) {
+        self.sources.lock().remove(source);
+    }
+
+    pub(crate) fn source_states(&self) -> BTreeMap<Arc<str>, SourceState> {
+        self.sources.lock().clone()
+    }
+
+    /// Time since processing started
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::collections::{BTreeMap, HashMap};
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::groups::WILDCARD;
+
+// Counters kept for each wildcard, more than are reported so the top values are accurate
+const CAPACITY: usize = 32;
+// Values reported for each wildcard
+const TOP_VALUES: usize = 5;
+// Longer values are truncated so a few huge tokens can't pin a lot of memory
+const MAX_VALUE_LEN: usize = 256;
+
+/// Approximate most frequent values of a stream using the Space-Saving algorithm
+///
+/// At most `capacity` values are counted. When a new value arrives and every counter is in use
+/// the least frequent value is replaced and the newcomer inherits its count, so counts are upper
+/// bounds which are exact for any value seen more often than total / capacity times.
+#[derive(Clone, Debug)]
+pub(crate) struct TopK {
+    capacity: usize,
+    counts: HashMap<String, u64>,
+}
+
+impl TopK {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counts: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, value: &str) {
+        if let Some(count) = self.counts.get_mut(value) {
+            *count += 1;
+            return;
+        }
+        let mut count = 1;
+        if self.counts.len() >= self.capacity {
+            if let Some((evicted, min)) = self
+                .counts
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(value, count)| (value.clone(), *count))
+            {
+                self.counts.remove(&evicted);
+                count += min;
+            }
+        }
+        self.counts.insert(value.to_string(), count);
+    }
+
+    /// The `n` most frequent values, most frequent first
+    pub(crate) fn top(&self, n: usize) -> Vec<ValueCount> {
+        self.counts
+            .iter()
+            .sorted_by(|a, b| Ord::cmp(b.1, a.1).then_with(|| Ord::cmp(a.0, b.0)))
+            .take(n)
+            .map(|(value, count)| {
+                ValueCount {
+                    value: value.clone(),
+                    count: *count,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ValueCount {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Most frequent values filling one wildcard of a template
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ParamSummary {
+    /// Index of the wildcard's token in the template
+    pub position: usize,
+    pub values: Vec<ValueCount>,
+}
+
+/// Values seen at each token position of a group's lines
+///
+/// Positions are only counted while they are wildcards. When a position becomes a wildcard after
+/// the group formed it is seeded from the group's recent lines, older lines aren't counted.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ParamStats {
+    positions: BTreeMap<usize, TopK>,
+}
+
+impl ParamStats {
+    /// Count the tokens of `line` which fill the wildcards of `template`, `earlier` holds the
+    /// group's recent lines for seeding new wildcards
+    pub(crate) fn record<'a>(
+        &mut self,
+        template: &str,
+        line: &str,
+        earlier: impl Iterator<Item = &'a str> + Clone,
+    ) {
+        let wildcards = template
+            .split_whitespace()
+            .enumerate()
+            .filter(|(_, token)| *token == WILDCARD);
+        for (position, _) in wildcards {
+            let top = self.positions.entry(position).or_insert_with(|| {
+                let mut top = TopK::new(CAPACITY);
+                for line in earlier.clone() {
+                    if let Some(value) = token(line, position) {
+                        top.insert(value);
+                    }
+                }
+                top
+            });
+            // Lines are only clustered with templates of the same length so this should be found
+            if let Some(value) = token(line, position) {
+                top.insert(value);
+            }
+        }
+    }
+
+    /// Top values of each wildcard currently in `template`
+    pub(crate) fn summaries(&self, template: &str) -> Vec<ParamSummary> {
+        template
+            .split_whitespace()
+            .enumerate()
+            .filter(|(_, token)| *token == WILDCARD)
+            .map(|(position, _)| {
+                ParamSummary {
+                    position,
+                    values: self
+                        .positions
+                        .get(&position)
+                        .map(|top| top.top(TOP_VALUES))
+                        .unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Token at `position` of `line`, truncated to a reasonable length
+fn token(line: &str, position: usize) -> Option<&str> {
+    let value = line.split_whitespace().nth(position)?;
+    match value.char_indices().nth(MAX_VALUE_LEN) {
+        Some((end, _)) => Some(&value[..end]),
+        None => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(top: &TopK, n: usize) -> Vec<(String, u64)> {
+        top.top(n).into_iter().map(|v| (v.value, v.count)).collect()
+    }
+
+    #[test]
+    fn counts_are_exact_within_capacity() {
+        let mut top = TopK::new(4);
+        for value in ["a", "b", "a", "c", "a", "b"] {
+            top.insert(value);
+        }
+        assert_eq!(
+            counts(&top, 2),
+            vec![("a".to_string(), 3), ("b".to_string(), 2)]
+        );
+        assert_eq!(top.top(10).len(), 3);
+    }
+
+    #[test]
+    fn heavy_hitters_survive_more_distinct_values_than_capacity() {
+        let capacity = 8;
+        let mut top = TopK::new(capacity);
+        let mut total = 0u64;
+        for i in 0..500 {
+            top.insert(&format!("noise-{}", i));
+            total += 1;
+            let mut insert = |value: &str| {
+                top.insert(value);
+                total += 1;
+            };
+            if i % 5 < 3 {
+                insert("a");
+            }
+            if i % 5 < 2 {
+                insert("b");
+            }
+            if i % 5 == 0 {
+                insert("c");
+            }
+        }
+        // Values seen more than total / capacity times are always kept, with counts overestimated
+        // by at most that much
+        let bound = total / capacity as u64;
+        let values = counts(&top, 2);
+        assert_eq!(values[0].0, "a");
+        assert_eq!(values[1].0, "b");
+        assert!((300..=300 + bound).contains(&values[0].1), "{:?}", values);
+        assert!((200..=200 + bound).contains(&values[1].1), "{:?}", values);
+        assert_eq!(top.top(usize::MAX).len(), capacity);
+    }
+
+    #[test]
+    fn summarises_wildcard_positions() {
+        let mut stats = ParamStats::default();
+        let template = format!("user {} logged in from {}", WILDCARD, WILDCARD);
+        for line in ["user bob logged in from web", "user amy logged in from web"] {
+            stats.record(&template, line, [].into_iter());
+        }
+        let summaries = stats.summaries(&template);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].position, 1);
+        assert_eq!(summaries[0].values.len(), 2);
+        assert_eq!(summaries[1].position, 5);
+        assert_eq!(summaries[1].values[0].value, "web");
+        assert_eq!(summaries[1].values[0].count, 2);
+    }
+}
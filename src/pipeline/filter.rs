@@ -0,0 +1,188 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::sync::Arc;
+
+use clap::ArgEnum;
+use regex::Regex;
+
+use super::Stage;
+use crate::{args::Args, metrics::Metrics, sources::LogRecord};
+
+// Fields checked, in order, for the severity of a parsed line
+const LEVEL_FIELDS: [&str; 5] = ["level", "lvl", "severity", "log.level", "loglevel"];
+
+/// Log severities, ordered from least to most severe
+#[derive(ArgEnum, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    /// Interpret the common spellings of a severity
+    pub(crate) fn parse(level: &str) -> Option<Self> {
+        match level.trim().to_ascii_lowercase().as_str() {
+            "trace" | "trc" => Some(Level::Trace),
+            "debug" | "dbg" => Some(Level::Debug),
+            "info" | "inf" | "information" | "notice" => Some(Level::Info),
+            "warn" | "wrn" | "warning" => Some(Level::Warn),
+            "error" | "err" | "eror" => Some(Level::Error),
+            "fatal" | "critical" | "crit" | "panic" | "alert" | "emerg" | "emergency" => {
+                Some(Level::Fatal)
+            },
+            _ => None,
+        }
+    }
+
+    fn of(record: &LogRecord) -> Option<Self> {
+        LEVEL_FIELDS
+            .iter()
+            .find_map(|field| record.fields.get(*field))
+            .and_then(|level| Level::parse(level))
+    }
+}
+
+/// Drops lines which don't match the include patterns, match an exclude pattern, or are parsed with
+/// a level below the minimum. Lines without a recognisable level are kept.
+#[derive(Debug)]
+pub(crate) struct Filter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    min_level: Option<Level>,
+    metrics: Arc<Metrics>,
+}
+
+impl Filter {
+    /// Filtering is only enabled when at least one condition is given
+    pub(crate) fn from_args(args: &Args, metrics: Arc<Metrics>) -> Option<Self> {
+        if args.include.is_empty() && args.exclude.is_empty() && args.min_level.is_none() {
+            return None;
+        }
+        Some(Self {
+            include: args.include.clone(),
+            exclude: args.exclude.clone(),
+            min_level: args.min_level,
+            metrics,
+        })
+    }
+
+    fn keep(&self, record: &LogRecord) -> bool {
+        if !self.include.is_empty() && !self.include.iter().any(|re| re.is_match(&record.line)) {
+            return false;
+        }
+        if self.exclude.iter().any(|re| re.is_match(&record.line)) {
+            return false;
+        }
+        match (self.min_level, Level::of(record)) {
+            (Some(min), Some(level)) => level >= min,
+            _ => true,
+        }
+    }
+}
+
+impl Stage for Filter {
+    fn apply(&self, record: LogRecord) -> Option<LogRecord> {
+        if self.keep(&record) {
+            Some(record)
+        } else {
+            self.metrics.record_filtered();
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn filter(args: &[&str]) -> Option<Filter> {
+        let args = Args::parse_from(["lyretail"].iter().chain(args));
+        Filter::from_args(&args, Arc::new(Metrics::new(1)))
+    }
+
+    fn record(line: &str, fields: &[(&str, &str)]) -> LogRecord {
+        let mut record = LogRecord::new(Arc::from("test"), line, 0);
+        for (key, value) in fields {
+            record.fields.insert(key.to_string(), value.to_string());
+        }
+        record
+    }
+
+    fn keeps(filter: &Filter, line: &str) -> bool {
+        filter.keep(&record(line, &[]))
+    }
+
+    #[test]
+    fn disabled_without_conditions() {
+        assert!(filter(&[]).is_none());
+    }
+
+    #[test]
+    fn include_keeps_only_matching_lines() {
+        let filter = filter(&["--include=error", "--include=^warn"]).unwrap();
+        assert!(keeps(&filter, "an error occurred"));
+        assert!(keeps(&filter, "warn: disk low"));
+        assert!(!keeps(&filter, "all is well, warn later"));
+    }
+
+    #[test]
+    fn exclude_takes_precedence_over_include() {
+        let filter = filter(&["--include=request", "--exclude=healthcheck"]).unwrap();
+        assert!(keeps(&filter, "request to /api"));
+        assert!(!keeps(&filter, "request to /healthcheck"));
+        assert!(!keeps(&filter, "healthcheck ok"));
+    }
+
+    #[test]
+    fn min_level_drops_less_severe_lines() {
+        let filter = filter(&["--min-level=warn"]).unwrap();
+        assert!(!filter.keep(&record("x", &[("level", "info")])));
+        assert!(filter.keep(&record("x", &[("level", "WARNING")])));
+        assert!(filter.keep(&record("x", &[("level", "crit")])));
+    }
+
+    #[test]
+    fn level_is_read_from_the_first_known_field() {
+        let filter = filter(&["--min-level=warn"]).unwrap();
+        for field in LEVEL_FIELDS {
+            assert!(!filter.keep(&record("x", &[(field, "debug")])), "{}", field);
+            assert!(filter.keep(&record("x", &[(field, "error")])), "{}", field);
+        }
+        // `level` is checked before `severity`
+        assert!(!filter.keep(&record("x", &[("level", "debug"), ("severity", "error")])));
+        assert!(filter.keep(&record("x", &[("lvl", "error"), ("loglevel", "trace")])));
+    }
+
+    #[test]
+    fn unknown_or_missing_levels_are_kept() {
+        let filter = filter(&["--min-level=error"]).unwrap();
+        assert!(filter.keep(&record("x", &[("level", "verbose")])));
+        assert!(filter.keep(&record("x", &[("lvl", "")])));
+        assert!(filter.keep(&record("x", &[("msg", "debug")])));
+        assert!(keeps(&filter, "debug: no fields"));
+    }
+
+    #[test]
+    fn dropped_lines_are_counted() {
+        let metrics = Arc::new(Metrics::new(1));
+        let args = Args::parse_from(["lyretail", "--exclude=noise"]);
+        let filter = Filter::from_args(&args, metrics.clone()).unwrap();
+        assert!(filter.apply(record("noise", &[])).is_none());
+        assert!(filter.apply(record("signal", &[])).is_some());
+        assert_eq!(metrics.filtered(), 1);
+    }
+}
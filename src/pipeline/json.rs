@@ -0,0 +1,120 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+/// Parse a JSON object into fields keyed by their dotted path, `None` if the line isn't an object
+pub(crate) fn parse_json(line: &str) -> Option<BTreeMap<String, String>> {
+    match serde_json::from_str::<Value>(line) {
+        Ok(value @ Value::Object(_)) => {
+            let mut fields = BTreeMap::new();
+            flatten("", value, &mut fields);
+            Some(fields)
+        },
+        _ => None,
+    }
+}
+
+fn flatten(path: &str, value: Value, fields: &mut BTreeMap<String, String>) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(&child(&key), value, fields);
+            }
+        },
+        Value::Array(values) => {
+            for (idx, value) in values.into_iter().enumerate() {
+                flatten(&child(&idx.to_string()), value, fields);
+            }
+        },
+        Value::String(s) => {
+            fields.insert(path.to_string(), s);
+        },
+        Value::Null => {
+            fields.insert(path.to_string(), String::new());
+        },
+        other => {
+            fields.insert(path.to_string(), other.to_string());
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field<'a>(fields: &'a BTreeMap<String, String>, key: &str) -> &'a str {
+        fields.get(key).map(String::as_str).unwrap_or_default()
+    }
+
+    #[test]
+    fn parses_flat_objects() {
+        let fields = parse_json(r#"{"level":"info","msg":"started"}"#).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(field(&fields, "level"), "info");
+        assert_eq!(field(&fields, "msg"), "started");
+    }
+
+    #[test]
+    fn flattens_nested_objects_to_dotted_paths() {
+        let fields = parse_json(r#"{"log":{"level":"warn","origin":{"file":"main.rs"}}}"#).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(field(&fields, "log.level"), "warn");
+        assert_eq!(field(&fields, "log.origin.file"), "main.rs");
+    }
+
+    #[test]
+    fn flattens_arrays_by_index() {
+        let fields = parse_json(r#"{"tags":["a","b"],"hosts":[{"name":"web"}]}"#).unwrap();
+        assert_eq!(field(&fields, "tags.0"), "a");
+        assert_eq!(field(&fields, "tags.1"), "b");
+        assert_eq!(field(&fields, "hosts.0.name"), "web");
+    }
+
+    #[test]
+    fn renders_scalars_as_text() {
+        let fields = parse_json(r#"{"port":8080,"ratio":0.5,"ok":true,"user":null}"#).unwrap();
+        assert_eq!(field(&fields, "port"), "8080");
+        assert_eq!(field(&fields, "ratio"), "0.5");
+        assert_eq!(field(&fields, "ok"), "true");
+        assert_eq!(fields.get("user").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn keeps_strings_unquoted_and_unescaped() {
+        let fields = parse_json(r#"{"msg":"say \"hi\"\n"}"#).unwrap();
+        assert_eq!(field(&fields, "msg"), "say \"hi\"\n");
+    }
+
+    #[test]
+    fn rejects_values_which_are_not_objects() {
+        assert!(parse_json(r#"["level","info"]"#).is_none());
+        assert!(parse_json(r#""started""#).is_none());
+        assert!(parse_json("42").is_none());
+        assert!(parse_json("null").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert!(parse_json("").is_none());
+        assert!(parse_json("level=info msg=started").is_none());
+        assert!(parse_json(r#"{"level":"info""#).is_none());
+        assert!(parse_json(r#"{"level":"info"} trailing"#).is_none());
+    }
+}
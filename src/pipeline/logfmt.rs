@@ -0,0 +1,126 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::collections::BTreeMap;
+
+/// Parse a logfmt or `key=value` line, `None` unless most of its tokens are pairs with a key
+///
+/// Values may be double quoted with backslash escapes, keys without a value are treated as flags
+/// and given the value "true". Requiring a majority of pairs keeps prose which happens to
+/// contain an `=` from being taken apart into flags.
+pub(crate) fn parse_logfmt(line: &str) -> Option<BTreeMap<String, String>> {
+    let mut fields = BTreeMap::new();
+    let mut tokens = 0;
+    let mut pairs = 0;
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        tokens += 1;
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            fields.insert(key, "true".to_string());
+            continue;
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            value.push(match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                other => other,
+                            });
+                        }
+                    },
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        if !key.is_empty() {
+            pairs += 1;
+            fields.insert(key, value);
+        }
+    }
+    if pairs * 2 > tokens {
+        Some(fields)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field<'a>(fields: &'a BTreeMap<String, String>, key: &str) -> &'a str {
+        fields.get(key).map(String::as_str).unwrap_or_default()
+    }
+
+    #[test]
+    fn parses_plain_pairs() {
+        let fields = parse_logfmt("level=info msg=started port=8080").unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(field(&fields, "level"), "info");
+        assert_eq!(field(&fields, "msg"), "started");
+        assert_eq!(field(&fields, "port"), "8080");
+    }
+
+    #[test]
+    fn parses_quoted_values() {
+        let fields = parse_logfmt(r#"msg="user logged in" user=bob empty="""#).unwrap();
+        assert_eq!(field(&fields, "msg"), "user logged in");
+        assert_eq!(field(&fields, "user"), "bob");
+        assert_eq!(field(&fields, "empty"), "");
+    }
+
+    #[test]
+    fn unescapes_quoted_values() {
+        let fields = parse_logfmt(r#"msg="say \"hi\"\n\tthen \\ leave" level=warn"#).unwrap();
+        assert_eq!(field(&fields, "msg"), "say \"hi\"\n\tthen \\ leave");
+        assert_eq!(field(&fields, "level"), "warn");
+    }
+
+    #[test]
+    fn treats_bare_keys_as_flags() {
+        let fields = parse_logfmt("level=debug retry=3 cached").unwrap();
+        assert_eq!(field(&fields, "cached"), "true");
+    }
+
+    #[test]
+    fn rejects_lines_without_pairs() {
+        assert!(parse_logfmt("").is_none());
+        assert!(parse_logfmt("just some words").is_none());
+    }
+
+    #[test]
+    fn rejects_prose_with_an_equals_sign() {
+        assert!(parse_logfmt("connection closed after retries=3 attempts").is_none());
+        assert!(parse_logfmt("x = y").is_none());
+    }
+
+    #[test]
+    fn rejects_pairs_without_keys() {
+        assert!(parse_logfmt("=foo").is_none());
+        assert!(parse_logfmt("=foo =bar level=info").is_none());
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+//! Stages which reshape records between the sources and the drain
+
+pub(crate) mod filter;
+pub(crate) mod json;
+pub(crate) mod logfmt;
+pub(crate) mod multiline;
+pub(crate) mod parse;
+
+use std::fmt::Debug;
+
+use tokio::sync::mpsc;
+use tracing::instrument;
+
+use crate::sources::LogRecord;
+
+/// A synchronous step applied to every record, returning `None` drops the record
+pub(crate) trait Stage: Debug + Send + Sync {
+    fn apply(&self, record: LogRecord) -> Option<LogRecord>;
+}
+
+/// Run each record from `input` through `stages` in order and send the survivors to `output`
+#[instrument(level = "trace", skip_all)]
+pub(crate) async fn transform(
+    stages: Vec<Box<dyn Stage>>,
+    mut input: mpsc::Receiver<LogRecord>,
+    output: mpsc::Sender<LogRecord>,
+) -> Result<(), anyhow::Error> {
+    while let Some(record) = input.recv().await {
+        let record = stages
+            .iter()
+            .try_fold(record, |record, stage| stage.apply(record));
+        if let Some(record) = record {
+            output.send(record).await?;
+        }
+    }
+    Ok(())
+}
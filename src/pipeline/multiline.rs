@@ -0,0 +1,156 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use regex::Regex;
+use tokio::{sync::mpsc, time, time::Instant};
+use tracing::{debug, instrument};
+
+use crate::{args::Args, metrics::Metrics, sources::LogRecord};
+
+/// How continuation lines are recognised and when a pending record is flushed
+#[derive(Clone, Debug)]
+pub(crate) struct MultilineConfig {
+    start: Option<Regex>,
+    indent: bool,
+    max_lines: usize,
+    timeout: Duration,
+}
+
+impl MultilineConfig {
+    /// Combining is only enabled when a start pattern or indentation continuation is requested
+    pub(crate) fn from_args(args: &Args) -> Option<Self> {
+        if args.multiline_start.is_none() && !args.multiline_indent {
+            return None;
+        }
+        Some(Self {
+            start: args.multiline_start.clone(),
+            indent: args.multiline_indent,
+            max_lines: args.multiline_max_lines,
+            timeout: args
+                .multiline_timeout
+                .to_std()
+                .expect("timeout is validated to be positive"),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Pending {
+    record: LogRecord,
+    lines: usize,
+    updated: Instant,
+}
+
+/// Joins continuation lines onto the record they belong to, tracking each source separately
+#[derive(Debug)]
+pub(crate) struct Combiner {
+    config: MultilineConfig,
+    metrics: Arc<Metrics>,
+    pending: HashMap<Arc<str>, Pending>,
+}
+
+impl Combiner {
+    pub(crate) fn new(config: MultilineConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            config,
+            metrics,
+            pending: HashMap::new(),
+        }
+    }
+
+    fn is_continuation(&self, line: &str) -> bool {
+        if self.config.indent && line.starts_with([' ', '\t']) {
+            return true;
+        }
+        match &self.config.start {
+            Some(start) => !start.is_match(line),
+            None => false,
+        }
+    }
+
+    /// Add a line, returning the previous record from the same source if this line starts a new one
+    pub(crate) fn push(&mut self, record: LogRecord, now: Instant) -> Option<LogRecord> {
+        let continues = self.is_continuation(&record.line);
+        if let Some(pending) = self.pending.get_mut(&record.source) {
+            if continues && pending.lines < self.config.max_lines {
+                pending.record.line.push('\n');
+                pending.record.line.push_str(&record.line);
+                pending.lines += 1;
+                pending.updated = now;
+                self.metrics.record_combined(1);
+                return None;
+            }
+        }
+        let pending = Pending {
+            record,
+            lines: 1,
+            updated: now,
+        };
+        self.pending
+            .insert(pending.record.source.clone(), pending)
+            .map(|p| p.record)
+    }
+
+    /// Records which haven't had a continuation line within the timeout
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<LogRecord> {
+        let timeout = self.config.timeout;
+        let expired = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now.duration_since(p.updated) >= timeout)
+            .map(|(source, _)| source.clone())
+            .collect::<Vec<_>>();
+        expired
+            .iter()
+            .filter_map(|source| self.pending.remove(source))
+            .map(|p| p.record)
+            .collect()
+    }
+
+    pub(crate) fn flush(&mut self) -> Vec<LogRecord> {
+        self.pending.drain().map(|(_, p)| p.record).collect()
+    }
+}
+
+/// Combine records from `input` into multi-line records sent to `output`
+#[instrument(level = "trace", skip_all)]
+pub(crate) async fn combine(
+    mut combiner: Combiner,
+    mut input: mpsc::Receiver<LogRecord>,
+    output: mpsc::Sender<LogRecord>,
+) -> Result<(), anyhow::Error> {
+    let mut ticker = time::interval(combiner.config.timeout);
+    loop {
+        tokio::select! {
+            maybe_record = input.recv() => {
+                match maybe_record {
+                    Some(record) => {
+                        if let Some(done) = combiner.push(record, Instant::now()) {
+                            output.send(done).await?;
+                        }
+                    },
+                    None => break,
+                }
+            }
+            _ = ticker.tick() => {
+                for done in combiner.expire(Instant::now()) {
+                    output.send(done).await?;
+                }
+            }
+        }
+    }
+    debug!("input closed, flushing pending records");
+    for done in combiner.flush() {
+        output.send(done).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use clap::ArgEnum;
+use tracing::trace;
+
+use super::{json::parse_json, logfmt::parse_logfmt, Stage};
+use crate::{args::Args, sources::LogRecord};
+
+/// Supported structured line formats
+#[derive(ArgEnum, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ParseMode {
+    Plain,
+    Json,
+    Logfmt,
+}
+
+impl ParseMode {
+    /// Field holding the text to cluster on when none is configured
+    fn default_message_field(&self) -> &'static str {
+        match self {
+            ParseMode::Plain => "",
+            ParseMode::Json => "message",
+            ParseMode::Logfmt => "msg",
+        }
+    }
+}
+
+/// Splits structured lines into the message which is clustered and the remaining fields, which are
+/// kept as attributes on the record. Lines which don't parse are passed through untouched.
+#[derive(Clone, Debug)]
+pub(crate) struct Parser {
+    mode: ParseMode,
+    message_field: String,
+}
+
+impl Parser {
+    /// Parsing is only enabled for structured modes
+    pub(crate) fn from_args(args: &Args) -> Option<Self> {
+        if args.parser == ParseMode::Plain {
+            return None;
+        }
+        Some(Self {
+            mode: args.parser,
+            message_field: args
+                .message_field
+                .clone()
+                .unwrap_or_else(|| args.parser.default_message_field().to_string()),
+        })
+    }
+}
+
+impl Stage for Parser {
+    fn apply(&self, mut record: LogRecord) -> Option<LogRecord> {
+        let fields = match self.mode {
+            ParseMode::Plain => None,
+            ParseMode::Json => parse_json(&record.line),
+            ParseMode::Logfmt => parse_logfmt(&record.line),
+        };
+        match fields {
+            Some(mut fields) => {
+                if let Some(message) = fields.remove(&self.message_field) {
+                    record.line = message;
+                }
+                record.fields.extend(fields);
+            },
+            None => trace!(?self.mode, "line did not parse, passing through"),
+        }
+        Some(record)
+    }
+}
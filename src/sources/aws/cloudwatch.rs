@@ -8,14 +8,15 @@
 // Server Side Public License along with this program.
 // If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use aws_sdk_cloudwatchlogs::{model::OrderBy, Client};
-use chrono::{DateTime, Duration, Utc};
-use tokio::sync::mpsc;
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use tokio_stream::StreamExt;
-use tracing::{instrument, debug, debug_span};
+use tracing::{instrument, debug, debug_span, warn};
 
-use crate::sources::LogReader;
+use crate::{metrics::SourceState, sources::{LogReader, LogRecord, RecordSender}};
 #[derive(Debug, Clone)]
 pub(crate) struct CloudwatchReader {
     client: Client,
@@ -74,7 +75,10 @@ impl CloudwatchReader {
 #[async_trait]
 impl LogReader for CloudwatchReader {
     #[instrument(level = "trace", skip_all)]
-    async fn read_logs(&self, lines: mpsc::UnboundedSender<String>) -> Result<(), anyhow::Error> {
+    async fn read_logs(
+        &self,
+        lines: RecordSender,
+    ) -> Result<(), anyhow::Error> {
         let log_stream_name = if let Some(log_stream) = self.log_stream.clone() {
             log_stream
         } else {
@@ -95,6 +99,7 @@ impl LogReader for CloudwatchReader {
                 .to_string()
         };
         debug!(%log_stream_name, "starting event fetching");
+        let source: Arc<str> = Arc::from(format!("{}/{}", self.log_group, log_stream_name));
         let mut event_fetcher = self
             .client
             .get_log_events()
@@ -106,19 +111,31 @@ impl LogReader for CloudwatchReader {
             .into_paginator()
             .send();
 
+        let mut sequence = 0u64;
+        // Failed pages are skipped, but the source is left marked as errored once done
+        let mut finished = SourceState::Eof;
+        lines.set_state(&source, SourceState::Paging);
         while let Some(event) = event_fetcher.next().await {
             let _span = debug_span!("sending line");
+            if let Err(e) = &event {
+                warn!(%e, "fetching log events failed");
+                finished = SourceState::Error;
+                lines.set_state(&source, SourceState::Error);
+            }
             if let Ok(log_events) = event {
                 for log_event in log_events.events().unwrap_or_default() {
-                    lines.send(
-                        log_event
-                            .message()
-                            .expect("log events have messages")
-                            .to_string(),
-                    )?;
+                    let record = LogRecord::new(
+                        source.clone(),
+                        log_event.message().expect("log events have messages"),
+                        sequence,
+                    )
+                    .with_event_time(log_event.timestamp().map(|ts| Utc.timestamp_millis(ts)));
+                    lines.send(record).await?;
+                    sequence += 1;
                 }
             }
         }
+        lines.set_state(&source, finished);
         Ok(())
     }
 }
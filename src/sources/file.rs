@@ -8,46 +8,554 @@
 // Server Side Public License along with this program.
 // If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
 
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZstdDecoder};
 use async_trait::async_trait;
+use parking_lot::Mutex;
 use tokio::{
-    fs::File,
-    io::{AsyncBufReadExt, BufReader},
-    sync::mpsc,
+    fs::{self, File},
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+    task,
+    time,
+};
+use tracing::{debug, instrument, trace, warn};
+
+use crate::{
+    metrics::SourceState,
+    sources::{LogReader, LogRecord, RecordSender},
 };
-use tracing::instrument;
 
-use crate::sources::LogReader;
+// Bounds for how long to wait between checks for new data once the end of a followed file is hit
+const MIN_BACKOFF: Duration = Duration::from_millis(50);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+// How often glob patterns are expanded again to pick up new files while following
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reads any number of files and glob patterns concurrently as one stream of lines
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub(crate) struct FileReader<'a> {
-    file: &'a PathBuf,
+pub(crate) struct FileReader {
+    patterns: Vec<PathBuf>,
     follow: bool,
 }
 
-impl<'a> FileReader<'a> {
+impl FileReader {
     #[instrument(level = "trace")]
-    pub(crate) fn new(file: &'a PathBuf, follow: bool) -> Self {
-        Self { file, follow }
+    pub(crate) fn new(patterns: Vec<PathBuf>, follow: bool) -> Self {
+        Self { patterns, follow }
+    }
+
+    fn has_globs(&self) -> bool {
+        self.patterns.iter().any(|p| is_glob(p))
+    }
+
+    /// Expand the configured paths and glob patterns to the files currently matching them
+    fn expand(&self) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut paths = vec![];
+        for pattern in &self.patterns {
+            if !is_glob(pattern) {
+                paths.push(pattern.clone());
+                continue;
+            }
+            for entry in glob::glob(&pattern.to_string_lossy())? {
+                match entry {
+                    Ok(path) if path.is_file() => paths.push(path),
+                    Ok(_) => {},
+                    Err(e) => warn!(%e, "unable to read glob match"),
+                }
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
     }
 }
 
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
 #[async_trait]
-impl LogReader for FileReader<'_> {
+impl LogReader for FileReader {
+    /// Read every matching file into the channel, while following new files matching a glob are
+    /// picked up as they appear
     #[instrument(level = "trace", skip_all)]
-    async fn read_logs(
-        &self,
-        drain_writer: mpsc::UnboundedSender<String>,
-    ) -> Result<(), anyhow::Error> {
-        let mut reader = Box::pin(BufReader::new(File::open(self.file).await.unwrap()));
-        let mut buffer = String::new();
-        while let Ok(b) = reader.read_line(&mut buffer).await {
-            if b == 0 && !self.follow {
+    async fn read_logs(&self, drain_writer: RecordSender) -> Result<(), anyhow::Error> {
+        let mut seen = HashSet::new();
+        let reading = Arc::new(Mutex::new(HashSet::new()));
+        let mut handles = vec![];
+        loop {
+            for path in self.expand()? {
+                if !seen.insert(path.clone()) {
+                    continue;
+                }
+                // Rotation renames a file which was already read to a name which may match a glob
+                if let Ok(meta) = fs::metadata(&path).await {
+                    if matches!(file_id(&meta), Some(id) if reading.lock().contains(&id)) {
+                        debug!(?path, "file was already read under another path");
+                        continue;
+                    }
+                }
+                debug!(?path, "reading file");
+                let file = TailedFile::new(path, self.follow, reading.clone());
+                let writer = drain_writer.clone();
+                handles.push(task::spawn(async move {
+                    writer.set_state(&file.source, SourceState::Reading);
+                    // A file which can't be read is only marked as failed, the others carry on
+                    match file.read_lines(writer.clone()).await {
+                        Ok(_) => {
+                            writer.set_state(&file.source, SourceState::Eof);
+                            Ok(())
+                        },
+                        Err(e) => {
+                            warn!(%e, ?file.path, "reading file failed");
+                            writer.set_state(&file.source, SourceState::Error);
+                            Err(e.context(format!("unable to read {}", file.path.display())))
+                        },
+                    }
+                }));
+            }
+            if !self.follow || !self.has_globs() {
                 break;
             }
-            drain_writer.send(buffer.clone())?;
-            buffer.clear();
+            time::sleep(RESCAN_INTERVAL).await;
+        }
+        // Every file is read to the end before the first failure, if any, is returned
+        let mut failure = None;
+        for handle in handles {
+            if let Err(e) = handle
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|res| res)
+            {
+                failure.get_or_insert(e);
+            }
+        }
+        failure.map_or(Ok(()), Err)
+    }
+}
+
+/// Compression formats recognised by their leading magic bytes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if is_bzip2(magic) {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// "BZh" alone is a plausible start for a text file, so also check the block size digit and the
+/// magic which starts the first block, or the end of stream of an empty file
+fn is_bzip2(magic: &[u8]) -> bool {
+    match magic {
+        [b'B', b'Z', b'h', b'1'..=b'9', rest @ ..] => {
+            rest.starts_with(b"1AY&SY") || rest.starts_with(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90])
+        },
+        _ => false,
+    }
+}
+
+type LineReader = Box<dyn AsyncBufRead + Unpin + Send>;
+
+/// A single file being read, and followed if requested
+#[derive(Debug, Clone)]
+struct TailedFile {
+    path: PathBuf,
+    source: Arc<str>,
+    follow: bool,
+    /// Ids of every file read so far by any path of the same reader
+    reading: Arc<Mutex<HashSet<u64>>>,
+}
+
+impl TailedFile {
+    fn new(path: PathBuf, follow: bool, reading: Arc<Mutex<HashSet<u64>>>) -> Self {
+        let source = Arc::from(path.display().to_string());
+        Self {
+            path,
+            source,
+            follow,
+            reading,
+        }
+    }
+
+    /// Claim the file with `id` for this path, false when another path already read it
+    fn claim(&self, id: Option<u64>) -> bool {
+        match id {
+            Some(id) => self.reading.lock().insert(id),
+            None => true,
+        }
+    }
+
+    /// Open the file, transparently decompressing it when it starts with a known magic number
+    async fn open(&self) -> Result<(LineReader, Option<u64>, Compression), io::Error> {
+        let file = File::open(&self.path).await?;
+        let id = file_id(&file.metadata().await?);
+        let mut reader = BufReader::new(file);
+        let compression = Compression::detect(reader.fill_buf().await?);
+        let reader: LineReader = match compression {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => {
+                let mut decoder = GzipDecoder::new(reader);
+                // Concatenated gzip members are common for rotated logs
+                decoder.multiple_members(true);
+                Box::new(BufReader::new(decoder))
+            },
+            Compression::Zstd => Box::new(BufReader::new(ZstdDecoder::new(reader))),
+            Compression::Bzip2 => Box::new(BufReader::new(BzDecoder::new(reader))),
+            Compression::Xz => Box::new(BufReader::new(XzDecoder::new(reader))),
+        };
+        Ok((reader, id, compression))
+    }
+
+    /// Read the line in `buffer`, which started at `offset`, as a record
+    ///
+    /// Logs aren't always valid UTF-8, so invalid bytes are replaced rather than failing the file.
+    fn record(&self, buffer: &[u8], offset: u64) -> LogRecord {
+        LogRecord::new(
+            self.source.clone(),
+            &String::from_utf8_lossy(buffer),
+            offset,
+        )
+    }
+
+    /// Read lines from the file, when following this behaves like `tail -F` by waiting for the path
+    /// to appear, reopening it if it is replaced or truncated and backing off while waiting for
+    /// more data
+    #[instrument(level = "trace", skip_all, fields(path = ?self.path))]
+    async fn read_lines(&self, drain_writer: RecordSender) -> Result<(), anyhow::Error> {
+        let mut backoff = MIN_BACKOFF;
+        // Whether the file has caught up and is being waited on, so the state only changes once
+        let mut waiting = false;
+        let (mut reader, mut id, compression) = loop {
+            match self.open().await {
+                Ok(opened) => break opened,
+                Err(e) if self.follow && e.kind() == io::ErrorKind::NotFound => {
+                    if !waiting {
+                        trace!(%e, "file doesn't exist yet, waiting");
+                        drain_writer.set_state(&self.source, SourceState::Following);
+                        waiting = true;
+                    }
+                    time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                },
+                Err(e) => return Err(e.into()),
+            }
+        };
+        if !self.claim(id) {
+            debug!("file was already read under another path");
+            return Ok(());
+        }
+        // Compressed files are archives which won't grow, and offsets into the decompressed stream
+        // can't be compared with the file size to spot truncation, so they are only read once
+        let follow = self.follow && compression == Compression::None;
+        if compression != Compression::None {
+            debug!(?compression, "decompressing file");
+        }
+        let mut position = 0u64;
+        let mut buffer = vec![];
+        loop {
+            let b = reader.read_until(b'\n', &mut buffer).await?;
+            position += b as u64;
+            let offset = position - buffer.len() as u64;
+            if b > 0 && waiting {
+                drain_writer.set_state(&self.source, SourceState::Reading);
+                waiting = false;
+            }
+            // When following, a line without its newline is still being written so wait for the rest
+            if b > 0 && (buffer.ends_with(b"\n") || !follow) {
+                drain_writer.send(self.record(&buffer, offset)).await?;
+                buffer.clear();
+                backoff = MIN_BACKOFF;
+                continue;
+            }
+            if b == 0 && !follow {
+                break;
+            }
+            if !waiting {
+                drain_writer.set_state(&self.source, SourceState::Following);
+                waiting = true;
+            }
+            match fs::metadata(&self.path).await {
+                Ok(meta) if file_id(&meta) != id || meta.len() < position => {
+                    debug!("file was rotated or truncated, reopening");
+                    // Lines written to the old file between the last read and the rotation would be
+                    // lost once it is closed, so finish it first, including any unterminated line
+                    loop {
+                        let b = reader.read_until(b'\n', &mut buffer).await?;
+                        position += b as u64;
+                        if !buffer.is_empty() && (b == 0 || buffer.ends_with(b"\n")) {
+                            let offset = position - buffer.len() as u64;
+                            drain_writer.send(self.record(&buffer, offset)).await?;
+                            buffer.clear();
+                        }
+                        if b == 0 {
+                            break;
+                        }
+                    }
+                    let (new_reader, new_id, _) = self.open().await?;
+                    // With a glob such as `app.log*` each rotated file is also followed by name,
+                    // and the file moved into its place was already read under the newer name
+                    if new_id != id && !self.claim(new_id) {
+                        debug!("file was already read under another path");
+                        break;
+                    }
+                    (reader, id) = (new_reader, new_id);
+                    position = 0;
+                    backoff = MIN_BACKOFF;
+                    continue;
+                },
+                Ok(_) => {},
+                // Between logrotate moving the old file away and the new one being created
+                Err(e) => trace!(%e, "file unavailable, waiting"),
+            }
+            time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
         }
         Ok(())
     }
 }
+
+/// Identity of the file behind a path, changes when logrotate moves a new file into place
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use tokio::{sync::mpsc, task::JoinHandle};
+
+    use super::*;
+    use crate::{metrics::Metrics, sources::record_channel};
+
+    // Long enough for a followed file to notice changes, short enough for a stuck test to fail
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lyretail-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn append(path: &Path, data: &[u8]) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data).unwrap();
+    }
+
+    fn spawn_reader(
+        path: &Path,
+        follow: bool,
+    ) -> (
+        JoinHandle<Result<(), anyhow::Error>>,
+        mpsc::Receiver<LogRecord>,
+    ) {
+        let (writer, reader) = record_channel(Arc::new(Metrics::new(16)));
+        let file = TailedFile::new(path.to_path_buf(), follow, Arc::default());
+        (
+            task::spawn(async move { file.read_lines(writer).await }),
+            reader,
+        )
+    }
+
+    async fn next_line(reader: &mut mpsc::Receiver<LogRecord>) -> (String, u64) {
+        let record = time::timeout(TIMEOUT, reader.recv())
+            .await
+            .expect("timed out waiting for a line")
+            .expect("reader stopped");
+        (record.line, record.offset)
+    }
+
+    #[test]
+    fn detects_compression() {
+        let cases: &[(&[u8], Compression)] = &[
+            (&[0x1f, 0x8b, 0x08, 0x00], Compression::Gzip),
+            (&[0x28, 0xb5, 0x2f, 0xfd, 0x00], Compression::Zstd),
+            (b"BZh91AY&SY\x00", Compression::Bzip2),
+            (&[0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00], Compression::Xz),
+            (b"2022-05-01 12:00:00 started\n", Compression::None),
+            (b"BZh9 is not a block\n", Compression::None),
+            (&[0x1f], Compression::None),
+            (&[0xfd, b'7', b'z', b'X', b'Z'], Compression::None),
+            (b"", Compression::None),
+        ];
+        for (magic, expected) in cases {
+            assert_eq!(Compression::detect(magic), *expected, "{:?}", magic);
+        }
+    }
+
+    #[test]
+    fn detects_bzip2() {
+        let cases: &[(&[u8], bool)] = &[
+            (b"BZh91AY&SY", true),
+            (b"BZh11AY&SY", true),
+            // An empty stream is the header followed by the end of stream magic
+            (
+                &[b'B', b'Z', b'h', b'9', 0x17, 0x72, 0x45, 0x38, 0x50, 0x90],
+                true,
+            ),
+            (b"BZh01AY&SY", false),
+            (b"BZh", false),
+            (b"BZh9", false),
+            (b"BZh9 text", false),
+            (b"BZhello world\n", false),
+            (b"bzh91AY&SY", false),
+        ];
+        for (magic, expected) in cases {
+            assert_eq!(
+                is_bzip2(magic),
+                *expected,
+                "{:?}",
+                String::from_utf8_lossy(magic)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_lines_with_offsets() {
+        let dir = temp_dir("offsets");
+        let path = dir.join("app.log");
+        append(&path, b"one\r\ntwo\nlast");
+        let (handle, mut reader) = spawn_reader(&path, false);
+        assert_eq!(next_line(&mut reader).await, ("one".to_string(), 0));
+        assert_eq!(next_line(&mut reader).await, ("two".to_string(), 5));
+        // Without following, an unterminated last line is still read
+        assert_eq!(next_line(&mut reader).await, ("last".to_string(), 9));
+        handle.await.unwrap().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_is_replaced() {
+        let dir = temp_dir("utf8");
+        let path = dir.join("app.log");
+        append(&path, b"bad \xff\xfe byte\nafter\n");
+        let (handle, mut reader) = spawn_reader(&path, false);
+        assert_eq!(next_line(&mut reader).await.0, "bad \u{fffd}\u{fffd} byte");
+        assert_eq!(next_line(&mut reader).await.0, "after");
+        handle.await.unwrap().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_file_fails_without_follow() {
+        let dir = temp_dir("missing");
+        let (handle, _reader) = spawn_reader(&dir.join("app.log"), false);
+        assert!(handle.await.unwrap().is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn following_waits_for_missing_file() {
+        let dir = temp_dir("wait");
+        let path = dir.join("app.log");
+        let (handle, mut reader) = spawn_reader(&path, true);
+        time::sleep(MIN_BACKOFF * 2).await;
+        append(&path, b"created\n");
+        assert_eq!(next_line(&mut reader).await, ("created".to_string(), 0));
+        handle.abort();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn following_waits_for_partial_line() {
+        let dir = temp_dir("partial");
+        let path = dir.join("app.log");
+        append(&path, b"first\npart");
+        let (handle, mut reader) = spawn_reader(&path, true);
+        assert_eq!(next_line(&mut reader).await, ("first".to_string(), 0));
+        time::sleep(MIN_BACKOFF * 2).await;
+        assert!(reader.try_recv().is_err());
+        append(&path, b"ial\n");
+        assert_eq!(next_line(&mut reader).await, ("partial".to_string(), 6));
+        handle.abort();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn following_reopens_rotated_file() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("app.log");
+        append(&path, b"before\n");
+        let (handle, mut reader) = spawn_reader(&path, true);
+        assert_eq!(next_line(&mut reader).await.0, "before");
+        // Lines written to the old file after it is moved away are read before the new file
+        let rotated = dir.join("app.log.1");
+        std::fs::rename(&path, &rotated).unwrap();
+        append(&rotated, b"late\n");
+        append(&path, b"after\n");
+        assert_eq!(next_line(&mut reader).await.0, "late");
+        assert_eq!(next_line(&mut reader).await, ("after".to_string(), 0));
+        handle.abort();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn following_glob_skips_rotated_files() {
+        let dir = temp_dir("glob");
+        let path = dir.join("app.log");
+        append(&path, b"before\n");
+        let (writer, mut reader) = record_channel(Arc::new(Metrics::new(16)));
+        let files = FileReader::new(vec![dir.join("app.log*")], true);
+        let handle = task::spawn(async move { files.read_logs(writer).await });
+        assert_eq!(next_line(&mut reader).await.0, "before");
+        std::fs::rename(&path, dir.join("app.log.1")).unwrap();
+        append(&path, b"after\n");
+        assert_eq!(next_line(&mut reader).await.0, "after");
+        // The rescan finds app.log.1, which was read as app.log
+        time::sleep(RESCAN_INTERVAL + MAX_BACKOFF).await;
+        assert!(reader.try_recv().is_err());
+        handle.abort();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn following_rereads_truncated_file() {
+        let dir = temp_dir("truncate");
+        let path = dir.join("app.log");
+        append(&path, b"one\ntwo\n");
+        let (handle, mut reader) = spawn_reader(&path, true);
+        assert_eq!(next_line(&mut reader).await.0, "one");
+        assert_eq!(next_line(&mut reader).await.0, "two");
+        std::fs::write(&path, b"new\n").unwrap();
+        assert_eq!(next_line(&mut reader).await, ("new".to_string(), 0));
+        handle.abort();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{io, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+    time,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    metrics::SourceState,
+    sources::{syslog, LogRecord, RecordSender},
+};
+
+// Longest line read from a connection in one piece, longer lines are split
+const MAX_LINE_LEN: usize = 64 * 1024;
+// Bounds for how long to wait before accepting again while out of file descriptors or memory
+const MIN_BACKOFF: Duration = Duration::from_millis(50);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Turns a message read from a connection into a record, given the connection's source and the
+/// message's offset into it
+pub(crate) type Parse = fn(Arc<str>, &str, u64) -> LogRecord;
+
+/// How the bytes of a connection are split into messages
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Framing {
+    /// Each line is a message
+    Lines,
+    /// Octet counted or newline framed syslog messages, picked per message
+    Syslog,
+}
+
+impl Framing {
+    /// Read the next message into `frame`, returning how many bytes it took up on the connection
+    /// or 0 once the connection is closed
+    async fn read<R: AsyncBufRead + Unpin>(
+        self,
+        reader: &mut R,
+        frame: &mut Vec<u8>,
+    ) -> Result<usize, io::Error> {
+        match self {
+            Framing::Lines => {
+                frame.clear();
+                read_line(reader, frame, MAX_LINE_LEN).await
+            },
+            Framing::Syslog => syslog::read_frame(reader, frame).await,
+        }
+    }
+}
+
+/// A socket which accepts connections
+#[async_trait]
+pub(crate) trait Listener: Send + 'static {
+    type Stream: AsyncRead + Unpin + Send + 'static;
+
+    /// Wait for the next connection, along with its peer's address when it has a useful one
+    async fn next_connection(&self) -> Result<(Self::Stream, Option<String>), io::Error>;
+}
+
+#[async_trait]
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    async fn next_connection(&self) -> Result<(Self::Stream, Option<String>), io::Error> {
+        let (stream, peer) = self.accept().await?;
+        Ok((stream, Some(peer.to_string())))
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    /// Unix socket peers are rarely bound to a path of their own, so none is given
+    async fn next_connection(&self) -> Result<(Self::Stream, Option<String>), io::Error> {
+        let (stream, _) = self.accept().await?;
+        Ok((stream, None))
+    }
+}
+
+/// Accept connections until the listener fails, reading each on its own task
+///
+/// Each connection is its own source, labelled with `name` followed by the peer's address or,
+/// when it has none, the connection's number. Sources are dropped once their connection closes so
+/// a long running listener doesn't accumulate them. Connections are closed when this returns or is
+/// dropped, so their senders can't keep the input open after the listener has failed.
+pub(crate) async fn accept_connections<L: Listener>(
+    listener: L,
+    name: Arc<str>,
+    framing: Framing,
+    parse: Parse,
+    drain_writer: RecordSender,
+) -> Result<(), anyhow::Error> {
+    let mut connections = JoinSet::new();
+    let mut accepted = 0u64;
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let (stream, peer) = tokio::select! {
+            next = listener.next_connection() => match next {
+                Ok(next) => next,
+                Err(e) if is_transient(&e) => {
+                    warn!(%e, %name, "unable to accept a connection, retrying");
+                    time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                },
+                Err(e) => return Err(e.into()),
+            },
+            // Finished connections are collected so the set only holds open ones
+            _ = connections.join_one(), if !connections.is_empty() => continue,
+        };
+        backoff = MIN_BACKOFF;
+        accepted += 1;
+        let source: Arc<str> = match peer {
+            Some(peer) => Arc::from(format!("{} {}", name, peer)),
+            None => Arc::from(format!("{} #{}", name, accepted)),
+        };
+        debug!(%source, "connection accepted");
+        let writer = drain_writer.clone();
+        connections.spawn(async move {
+            writer.set_state(&source, SourceState::Reading);
+            let reader = BufReader::new(stream);
+            match read_connection(reader, source.clone(), framing, parse, writer.clone()).await {
+                Ok(_) => debug!(%source, "connection closed"),
+                Err(e) => warn!(%e, %source, "connection failed"),
+            }
+            writer.remove_source(&source);
+        });
+    }
+}
+
+/// Whether accepting failed for a reason which passes by itself, such as a peer which gave up before
+/// it was accepted or running out of file descriptors while many connections are open
+fn is_transient(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    let exhausted = matches!(
+        e.raw_os_error(),
+        Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM)
+    );
+    #[cfg(not(unix))]
+    let exhausted = false;
+    exhausted
+        || matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::WouldBlock
+        )
+}
+
+/// Send each message until the connection is closed, skipping blank ones
+async fn read_connection<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    source: Arc<str>,
+    framing: Framing,
+    parse: Parse,
+    drain_writer: RecordSender,
+) -> Result<(), anyhow::Error> {
+    let mut frame = vec![];
+    let mut offset = 0u64;
+    loop {
+        let read = framing.read(&mut reader, &mut frame).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&frame);
+        if !text.trim().is_empty() {
+            drain_writer
+                .send(parse(source.clone(), &text, offset))
+                .await?;
+        }
+        offset += read as u64;
+    }
+}
+
+/// Append up to and including the next newline to `buf`, reading at most `max` bytes so a
+/// sender which never ends its line can't grow the buffer without bound. A longer line is split,
+/// the rest of it is read by the next call.
+pub(crate) async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> Result<usize, io::Error> {
+    (&mut *reader).take(max as u64).read_until(b'\n', buf).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, future};
+
+    use parking_lot::Mutex;
+    use tokio::{
+        io::{AsyncWriteExt, DuplexStream},
+        sync::mpsc,
+        task,
+    };
+
+    use super::*;
+    use crate::{metrics::Metrics, sources::record_channel};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Hands out the scripted results in order, then waits forever
+    struct Scripted(Mutex<VecDeque<Result<DuplexStream, io::Error>>>);
+
+    #[async_trait]
+    impl Listener for Scripted {
+        type Stream = DuplexStream;
+
+        async fn next_connection(&self) -> Result<(Self::Stream, Option<String>), io::Error> {
+            let next = self.0.lock().pop_front();
+            match next {
+                Some(next) => next.map(|stream| (stream, None)),
+                None => future::pending().await,
+            }
+        }
+    }
+
+    fn accept(
+        script: Vec<Result<DuplexStream, io::Error>>,
+    ) -> (
+        task::JoinHandle<Result<(), anyhow::Error>>,
+        mpsc::Receiver<LogRecord>,
+        Arc<Metrics>,
+    ) {
+        let metrics = Arc::new(Metrics::new(16));
+        let (writer, reader) = record_channel(metrics.clone());
+        let listener = Scripted(Mutex::new(script.into()));
+        let handle = task::spawn(accept_connections(
+            listener,
+            Arc::from("test"),
+            Framing::Lines,
+            LogRecord::new,
+            writer,
+        ));
+        (handle, reader, metrics)
+    }
+
+    #[tokio::test]
+    async fn reads_lines_from_each_connection() {
+        let (mut first, server_first) = tokio::io::duplex(64);
+        let (mut second, server_second) = tokio::io::duplex(64);
+        let (handle, mut reader, _) = accept(vec![Ok(server_first), Ok(server_second)]);
+        first.write_all(b"one\n\n").await.unwrap();
+        let record = time::timeout(TIMEOUT, reader.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((&*record.source, record.line.as_str()), ("test #1", "one"));
+        second.write_all(b"two\n").await.unwrap();
+        let record = time::timeout(TIMEOUT, reader.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((&*record.source, record.line.as_str()), ("test #2", "two"));
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn retries_after_transient_accept_errors() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (handle, mut reader, _) = accept(vec![
+            Err(io::ErrorKind::ConnectionAborted.into()),
+            #[cfg(unix)]
+            Err(io::Error::from_raw_os_error(libc::EMFILE)),
+            Ok(server),
+        ]);
+        client.write_all(b"accepted\n").await.unwrap();
+        let record = time::timeout(TIMEOUT, reader.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.line, "accepted");
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn closes_connections_when_the_listener_fails() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (handle, mut reader, _) = accept(vec![
+            Ok(server),
+            Err(io::ErrorKind::PermissionDenied.into()),
+        ]);
+        assert!(handle.await.unwrap().is_err());
+        // Every sender is gone once the open connection is closed, so the input ends
+        assert!(time::timeout(TIMEOUT, reader.recv())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(client.write_all(b"late\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn drops_sources_of_closed_connections() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (handle, mut reader, metrics) = accept(vec![Ok(server)]);
+        client.write_all(b"bye\n").await.unwrap();
+        let record = time::timeout(TIMEOUT, reader.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(metrics.source_states().contains_key(&record.source));
+        drop(client);
+        time::timeout(TIMEOUT, async {
+            while metrics.source_states().contains_key(&record.source) {
+                time::sleep(MIN_BACKOFF).await;
+            }
+        })
+        .await
+        .unwrap();
+        handle.abort();
+    }
+}
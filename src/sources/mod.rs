@@ -12,23 +12,105 @@
 pub(crate) mod aws;
 
 pub(crate) mod file;
+pub(crate) mod listener;
+pub(crate) mod socket;
+pub(crate) mod stdin;
+pub(crate) mod syslog;
+
+use std::{collections::BTreeMap, sync::Arc};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use clap::ArgEnum;
 use tokio::sync::mpsc;
 
+use crate::metrics::{Metrics, SourceState};
+
 /// Supported source types
 #[derive(ArgEnum, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum SourceType {
     File,
+    Syslog,
+    Socket,
     #[cfg(feature = "aws")]
     Cloudwatch,
 }
 
+/// A line read by a source along with where and when it came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LogRecord {
+    /// Name of the source, such as the file path
+    pub source: Arc<str>,
+    /// Line text without its line terminator
+    pub line: String,
+    /// When lyretail read the line
+    pub ingested_at: DateTime<Utc>,
+    /// When the event happened, for sources which report it
+    pub event_time: Option<DateTime<Utc>>,
+    /// Byte offset of the line within its file, or a sequence number for sources without offsets
+    pub offset: u64,
+    /// Structured fields attached by the source or parsing
+    pub fields: BTreeMap<String, String>,
+}
+
+impl LogRecord {
+    pub(crate) fn new(source: Arc<str>, line: &str, offset: u64) -> Self {
+        Self {
+            source,
+            line: line.trim_end_matches(&['\r', '\n'][..]).to_string(),
+            ingested_at: Utc::now(),
+            event_time: None,
+            offset,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn with_event_time(mut self, event_time: Option<DateTime<Utc>>) -> Self {
+        self.event_time = event_time;
+        self
+    }
+
+    /// The time the event happened if known, otherwise when it was read
+    pub(crate) fn timestamp(&self) -> DateTime<Utc> {
+        self.event_time.unwrap_or(self.ingested_at)
+    }
+}
+
+/// Sending half of the bounded channel between sources and processing
+///
+/// Sending waits while the channel is full so a fast source can't outrun the drain, and every
+/// record is counted so the queue depth can be reported.
+#[derive(Clone, Debug)]
+pub(crate) struct RecordSender {
+    tx: mpsc::Sender<LogRecord>,
+    metrics: Arc<Metrics>,
+}
+
+impl RecordSender {
+    pub(crate) async fn send(&self, record: LogRecord) -> Result<(), anyhow::Error> {
+        self.metrics.record_queued();
+        self.tx.send(record).await?;
+        Ok(())
+    }
+
+    /// Report what the named source is doing
+    pub(crate) fn set_state(&self, source: &Arc<str>, state: SourceState) {
+        self.metrics.set_source_state(source, state);
+    }
+
+    /// Stop reporting a source which won't be read from again
+    pub(crate) fn remove_source(&self, source: &Arc<str>) {
+        self.metrics.remove_source(source);
+    }
+}
+
+/// Create the channel sources write records to, holding at most the metrics' queue capacity
+pub(crate) fn record_channel(metrics: Arc<Metrics>) -> (RecordSender, mpsc::Receiver<LogRecord>) {
+    let (tx, rx) = mpsc::channel(metrics.queue_capacity());
+    (RecordSender { tx, metrics }, rx)
+}
+
 #[async_trait]
 pub(crate) trait LogReader {
-    async fn read_logs(
-        &self,
-        drain_writer: mpsc::UnboundedSender<String>,
-    ) -> Result<(), anyhow::Error>;
+    async fn read_logs(&self, drain_writer: RecordSender) -> Result<(), anyhow::Error>;
 }
@@ -0,0 +1,214 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+#[cfg(unix)]
+use std::{io, os::unix::fs::FileTypeExt, path::Path};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+#[cfg(unix)]
+use tokio::{
+    fs,
+    net::{UnixListener, UnixStream},
+};
+use tokio::{net::TcpListener, task::JoinSet};
+use tracing::{debug, instrument};
+
+use crate::{
+    metrics::SourceState,
+    sources::{
+        listener::{accept_connections, Framing},
+        LogReader,
+        LogRecord,
+        RecordSender,
+    },
+};
+
+/// Accepts any number of TCP and/or Unix socket connections and reads newline delimited lines
+/// from all of them, each connection labelled as its own source
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct SocketReader {
+    tcp: Option<SocketAddr>,
+    unix: Option<PathBuf>,
+}
+
+impl SocketReader {
+    #[instrument(level = "trace")]
+    pub(crate) fn new(tcp: Option<SocketAddr>, unix: Option<PathBuf>) -> Self {
+        Self { tcp, unix }
+    }
+}
+
+#[async_trait]
+impl LogReader for SocketReader {
+    /// Listen on every configured socket until one of them fails
+    #[instrument(level = "trace", skip_all)]
+    async fn read_logs(&self, drain_writer: RecordSender) -> Result<(), anyhow::Error> {
+        // Both sockets are bound before either is read, so a failure to bind leaves nothing
+        // running which would keep the input open
+        let tcp = match self.tcp {
+            Some(addr) => {
+                let source: Arc<str> = Arc::from(format!("tcp {}", addr));
+                match TcpListener::bind(addr).await {
+                    Ok(listener) => Some((addr, source, listener)),
+                    Err(e) => {
+                        drain_writer.set_state(&source, SourceState::Error);
+                        return Err(e).with_context(|| format!("unable to listen on {}", source));
+                    },
+                }
+            },
+            None => None,
+        };
+        #[cfg(unix)]
+        let unix = match &self.unix {
+            Some(path) => {
+                let source: Arc<str> = Arc::from(format!("unix {}", path.display()));
+                match bind_unix(path).await {
+                    Ok(listener) => Some((SocketFile(path.clone()), source, listener)),
+                    Err(e) => {
+                        drain_writer.set_state(&source, SourceState::Error);
+                        return Err(e.context(format!("unable to listen on {}", source)));
+                    },
+                }
+            },
+            None => None,
+        };
+        #[cfg(not(unix))]
+        if let Some(path) = &self.unix {
+            return Err(anyhow!(
+                "unable to listen on unix {}: Unix sockets aren't supported on this platform",
+                path.display()
+            ));
+        }
+        let mut listeners = JoinSet::new();
+        if let Some((addr, source, listener)) = tcp {
+            debug!(%addr, "listening for tcp connections");
+            drain_writer.set_state(&source, SourceState::Listening);
+            listeners.spawn(accept_connections(
+                listener,
+                Arc::from("tcp"),
+                Framing::Lines,
+                LogRecord::new,
+                drain_writer.clone(),
+            ));
+        }
+        #[cfg(unix)]
+        if let Some((file, source, listener)) = unix {
+            debug!(path = ?file.0, "listening for unix socket connections");
+            drain_writer.set_state(&source, SourceState::Listening);
+            let writer = drain_writer.clone();
+            listeners.spawn(async move {
+                // Held until the listener stops, including when its task is aborted
+                let _file = file;
+                accept_connections(listener, source, Framing::Lines, LogRecord::new, writer).await
+            });
+        }
+        let res = listeners.join_one().await;
+        listeners.shutdown().await;
+        res?.unwrap_or(Ok(()))
+    }
+}
+
+/// Bind a Unix socket at `path`, replacing a socket left behind by an earlier run
+///
+/// A socket which still accepts connections belongs to a running process, so it is left alone
+/// and binding fails.
+#[cfg(unix)]
+async fn bind_unix(path: &Path) -> Result<UnixListener, anyhow::Error> {
+    match UnixStream::connect(path).await {
+        Ok(_) => return Err(anyhow!("another process is listening on the socket")),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            // Connecting to a file which isn't a socket is refused as well
+            if fs::symlink_metadata(path).await?.file_type().is_socket() {
+                debug!(?path, "removing stale socket");
+                fs::remove_file(path).await?;
+            }
+        },
+        Err(_) => {},
+    }
+    Ok(UnixListener::bind(path)?)
+}
+
+/// A Unix socket's path, removed when the listener stops so it isn't left behind for the next run
+#[cfg(unix)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            debug!(%e, path = ?self.0, "unable to remove socket");
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{metrics::Metrics, sources::record_channel};
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("lyretail-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn replaces_stale_socket() {
+        let path = socket_path("stale");
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let listener = bind_unix(&path).await.unwrap();
+        assert!(UnixStream::connect(&path).await.is_ok());
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_socket_in_use() {
+        let path = socket_path("in-use");
+        let _listener = UnixListener::bind(&path).unwrap();
+        assert!(bind_unix(&path).await.is_err());
+        assert!(UnixStream::connect(&path).await.is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn leaves_other_files_alone() {
+        let path = socket_path("file");
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(bind_unix(&path).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn removes_socket_when_stopped() {
+        let path = socket_path("stopped");
+        let (writer, _reader) = record_channel(Arc::new(Metrics::new(1)));
+        let reader = SocketReader::new(None, Some(path.clone()));
+        let handle = tokio::spawn(async move { reader.read_logs(writer).await });
+        while !path.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        // The listener's own task stops once it is next polled
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while path.exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
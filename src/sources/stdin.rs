@@ -0,0 +1,63 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+use tracing::{debug, instrument};
+
+use crate::{
+    metrics::SourceState,
+    sources::{LogReader, LogRecord, RecordSender},
+};
+
+/// Reads lines piped into the process until stdin is closed
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct StdinReader {}
+
+impl StdinReader {
+    #[instrument(level = "trace")]
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+}
+
+#[async_trait]
+impl LogReader for StdinReader {
+    #[instrument(level = "trace", skip_all)]
+    async fn read_logs(&self, drain_writer: RecordSender) -> Result<(), anyhow::Error> {
+        let source: Arc<str> = Arc::from("stdin");
+        let mut reader = BufReader::new(stdin());
+        let mut buffer = String::new();
+        let mut sequence = 0u64;
+        drain_writer.set_state(&source, SourceState::Reading);
+        loop {
+            let b = match reader.read_line(&mut buffer).await {
+                Ok(b) => b,
+                Err(e) => {
+                    drain_writer.set_state(&source, SourceState::Error);
+                    return Err(e.into());
+                },
+            };
+            if b == 0 {
+                debug!("stdin closed");
+                drain_writer.set_state(&source, SourceState::Eof);
+                break;
+            }
+            drain_writer
+                .send(LogRecord::new(source.clone(), &buffer, sequence))
+                .await?;
+            sequence += 1;
+            buffer.clear();
+        }
+        Ok(())
+    }
+}
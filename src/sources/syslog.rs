@@ -0,0 +1,681 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Utc};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt},
+    net::{TcpListener, UdpSocket},
+    task::JoinSet,
+    time::{self, Instant},
+};
+use tracing::{debug, instrument, trace};
+
+use crate::{
+    metrics::SourceState,
+    sources::{
+        listener::{accept_connections, read_line, Framing},
+        LogReader,
+        LogRecord,
+        RecordSender,
+    },
+};
+
+// Largest message accepted, datagrams can't be larger, octet counted frames claiming more are read
+// as newline framed and longer lines are split
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+// Digits in the length of the largest octet counted frame
+const MAX_LENGTH_DIGITS: usize = 5;
+// Length of an RFC 3164 timestamp such as "Oct  8 22:14:15"
+const BSD_TIMESTAMP_LEN: usize = 15;
+// Longest tag accepted before the ':' in an RFC 3164 message
+const MAX_TAG_LEN: usize = 48;
+// How long a host can go without sending a datagram before its source is dropped
+const PEER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+const FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+/// Receives syslog messages sent over UDP and/or TCP
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct SyslogReader {
+    udp: Option<SocketAddr>,
+    tcp: Option<SocketAddr>,
+}
+
+impl SyslogReader {
+    #[instrument(level = "trace")]
+    pub(crate) fn new(udp: Option<SocketAddr>, tcp: Option<SocketAddr>) -> Self {
+        Self { udp, tcp }
+    }
+}
+
+#[async_trait]
+impl LogReader for SyslogReader {
+    /// Listen on every configured socket until one of them fails
+    #[instrument(level = "trace", skip_all)]
+    async fn read_logs(&self, drain_writer: RecordSender) -> Result<(), anyhow::Error> {
+        // Both sockets are bound before either is read, so a failure to bind leaves nothing
+        // running which would keep the input open
+        let udp = match self.udp {
+            Some(addr) => {
+                let source: Arc<str> = Arc::from(format!("syslog udp {}", addr));
+                match UdpSocket::bind(addr).await {
+                    Ok(socket) => Some((addr, source, socket)),
+                    Err(e) => {
+                        drain_writer.set_state(&source, SourceState::Error);
+                        return Err(e).with_context(|| format!("unable to listen on {}", source));
+                    },
+                }
+            },
+            None => None,
+        };
+        let tcp = match self.tcp {
+            Some(addr) => {
+                let source: Arc<str> = Arc::from(format!("syslog tcp {}", addr));
+                match TcpListener::bind(addr).await {
+                    Ok(listener) => Some((addr, source, listener)),
+                    Err(e) => {
+                        drain_writer.set_state(&source, SourceState::Error);
+                        return Err(e).with_context(|| format!("unable to listen on {}", source));
+                    },
+                }
+            },
+            None => None,
+        };
+        let mut listeners = JoinSet::new();
+        if let Some((addr, source, socket)) = udp {
+            debug!(%addr, "listening for syslog datagrams");
+            drain_writer.set_state(&source, SourceState::Listening);
+            listeners.spawn(receive_datagrams(
+                socket,
+                PEER_TIMEOUT,
+                drain_writer.clone(),
+            ));
+        }
+        if let Some((addr, source, listener)) = tcp {
+            debug!(%addr, "listening for syslog connections");
+            drain_writer.set_state(&source, SourceState::Listening);
+            listeners.spawn(accept_connections(
+                listener,
+                Arc::from("syslog tcp"),
+                Framing::Syslog,
+                |source, text, offset| SyslogMessage::parse(text).into_record(source, offset),
+                drain_writer.clone(),
+            ));
+        }
+        let res = listeners.join_one().await;
+        listeners.shutdown().await;
+        res?.unwrap_or(Ok(()))
+    }
+}
+
+/// A host sending datagrams
+struct Peer {
+    source: Arc<str>,
+    sequence: u64,
+    last_seen: Instant,
+}
+
+/// Read one message per datagram, labelling each with the address it came from
+///
+/// Datagrams have no connection to close, so a host's source is dropped once it has sent nothing
+/// for `timeout` and a long running listener doesn't accumulate them.
+async fn receive_datagrams(
+    socket: UdpSocket,
+    timeout: std::time::Duration,
+    drain_writer: RecordSender,
+) -> Result<(), anyhow::Error> {
+    let mut peers: HashMap<IpAddr, Peer> = HashMap::new();
+    let mut buffer = vec![0u8; MAX_MESSAGE_LEN];
+    let mut expiry = time::interval(timeout / 2);
+    loop {
+        let (len, addr) = tokio::select! {
+            received = socket.recv_from(&mut buffer) => received?,
+            _ = expiry.tick() => {
+                peers.retain(|_, peer| {
+                    let idle = peer.last_seen.elapsed() >= timeout;
+                    if idle {
+                        trace!(source = %peer.source, "dropping idle peer");
+                        drain_writer.remove_source(&peer.source);
+                    }
+                    !idle
+                });
+                continue;
+            },
+        };
+        let peer = peers.entry(addr.ip()).or_insert_with(|| {
+            let source: Arc<str> = Arc::from(format!("syslog udp {}", addr.ip()));
+            drain_writer.set_state(&source, SourceState::Reading);
+            Peer {
+                source,
+                sequence: 0,
+                last_seen: Instant::now(),
+            }
+        });
+        peer.last_seen = Instant::now();
+        let text = String::from_utf8_lossy(&buffer[..len]);
+        drain_writer
+            .send(SyslogMessage::parse(&text).into_record(peer.source.clone(), peer.sequence))
+            .await?;
+        peer.sequence += 1;
+    }
+}
+
+/// Read the next message into `frame`, returning how many bytes it took up on the connection or 0
+/// once the connection is closed
+///
+/// Senders either prefix each message with its length in octets (RFC 6587 octet counting) or end
+/// it with a newline. An octet counted message starts with its length and a space followed by the
+/// '<' of its priority, anything else is read up to the next newline, so the framing is picked
+/// per message.
+pub(crate) async fn read_frame<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    frame: &mut Vec<u8>,
+) -> Result<usize, io::Error> {
+    frame.clear();
+    // What might be a length is kept in the frame, so a message which merely starts with a
+    // number is read whole
+    while let Some(byte) = next_byte(reader).await? {
+        frame.push(byte);
+        if !byte.is_ascii_digit() || frame.len() > MAX_LENGTH_DIGITS {
+            break;
+        }
+    }
+    if let Some(len) = octet_count(frame) {
+        if reader.fill_buf().await?.first() == Some(&b'<') {
+            let prefix = frame.len();
+            frame.clear();
+            frame.resize(len, 0);
+            reader.read_exact(frame).await?;
+            return Ok(prefix + len);
+        }
+    }
+    let read = frame.len();
+    if read == 0 || frame.ends_with(b"\n") {
+        return Ok(read);
+    }
+    Ok(read + read_line(reader, frame, MAX_MESSAGE_LEN - read).await?)
+}
+
+/// Length of an octet counted message from the digits and space before it
+fn octet_count(prefix: &[u8]) -> Option<usize> {
+    let digits = prefix.strip_suffix(b" ")?;
+    std::str::from_utf8(digits)
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|len| (1..=MAX_MESSAGE_LEN).contains(len))
+}
+
+/// Take the next byte from the reader, `None` once it is exhausted
+async fn next_byte<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<u8>, io::Error> {
+    let byte = reader.fill_buf().await?.first().copied();
+    if byte.is_some() {
+        reader.consume(1);
+    }
+    Ok(byte)
+}
+
+/// The parts of a syslog message lyretail keeps
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct SyslogMessage<'a> {
+    facility: Option<u8>,
+    severity: Option<u8>,
+    timestamp: Option<DateTime<Utc>>,
+    hostname: Option<&'a str>,
+    app: Option<&'a str>,
+    procid: Option<&'a str>,
+    message: &'a str,
+}
+
+impl<'a> SyslogMessage<'a> {
+    /// Parse an RFC 5424 or RFC 3164 message, text which is neither is kept whole as the message
+    fn parse(text: &'a str) -> Self {
+        let text = text.trim_end_matches(&['\r', '\n', '\0'][..]);
+        let (priority, rest) = match parse_priority(text) {
+            Some(parsed) => parsed,
+            None => {
+                return Self {
+                    message: text,
+                    ..Self::default()
+                }
+            },
+        };
+        let mut message = match rest.strip_prefix("1 ") {
+            Some(rest) => Self::parse_5424(rest),
+            None => Self::parse_3164(rest),
+        };
+        message.facility = Some(priority / 8);
+        message.severity = Some(priority % 8);
+        message
+    }
+
+    /// Parse what follows the priority and version of an RFC 5424 message
+    fn parse_5424(text: &'a str) -> Self {
+        let mut parts = text.splitn(6, ' ');
+        let mut next = || parts.next().filter(|part| *part != "-");
+        let timestamp = next()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            .map(|ts| ts.with_timezone(&Utc));
+        let hostname = next();
+        let app = next();
+        let procid = next();
+        let _msgid = next();
+        let message = skip_structured_data(parts.next().unwrap_or_default());
+        Self {
+            timestamp,
+            hostname,
+            app,
+            procid,
+            message: message.trim_start_matches('\u{feff}'),
+            ..Self::default()
+        }
+    }
+
+    /// Parse what follows the priority of an RFC 3164 message, "Mmm dd hh:mm:ss host tag: msg"
+    fn parse_3164(text: &'a str) -> Self {
+        let mut parsed = Self {
+            message: text,
+            ..Self::default()
+        };
+        if let Some(timestamp) = text.get(..BSD_TIMESTAMP_LEN).and_then(parse_bsd_timestamp) {
+            parsed.timestamp = Some(timestamp);
+            let rest = text[BSD_TIMESTAMP_LEN..].trim_start();
+            match rest.split_once(' ') {
+                Some((hostname, rest)) => {
+                    parsed.hostname = Some(hostname);
+                    parsed.message = rest;
+                },
+                None => parsed.message = rest,
+            }
+        }
+        // The tag is the program name, optionally followed by its pid in brackets, then a colon
+        let tag_end = parsed
+            .message
+            .char_indices()
+            .take(MAX_TAG_LEN)
+            .find(|(_, c)| matches!(c, ':' | '[' | ' '))
+            .filter(|(_, c)| *c != ' ');
+        if let Some((end, c)) = tag_end {
+            let (app, rest) = parsed.message.split_at(end);
+            let (procid, rest) = match c {
+                '[' => {
+                    match rest[1..].split_once(']') {
+                        Some((procid, rest)) => (Some(procid), rest),
+                        None => return parsed,
+                    }
+                },
+                _ => (None, rest),
+            };
+            if let Some(message) = rest.strip_prefix(':') {
+                parsed.app = Some(app).filter(|app| !app.is_empty());
+                parsed.procid = procid;
+                parsed.message = message.trim_start();
+            }
+        }
+        parsed
+    }
+
+    fn into_record(self, source: Arc<str>, offset: u64) -> LogRecord {
+        let mut record =
+            LogRecord::new(source, self.message, offset).with_event_time(self.timestamp);
+        let fields = [
+            ("hostname", self.hostname.map(str::to_string)),
+            ("app", self.app.map(str::to_string)),
+            ("procid", self.procid.map(str::to_string)),
+            (
+                "severity",
+                self.severity.map(|s| SEVERITIES[s as usize].to_string()),
+            ),
+            (
+                "facility",
+                self.facility.map(|f| FACILITIES[f as usize].to_string()),
+            ),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                record.fields.insert(name.to_string(), value);
+            }
+        }
+        trace!(?record, "syslog message");
+        record
+    }
+}
+
+/// Split "<PRI>" off the start of a message, priorities above local7.debug are invalid
+fn parse_priority(text: &str) -> Option<(u8, &str)> {
+    let (priority, rest) = text.strip_prefix('<')?.split_once('>')?;
+    if priority.is_empty() || priority.len() > 3 || !priority.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let priority = priority.parse::<u8>().ok().filter(|p| *p < 192)?;
+    Some((priority, rest))
+}
+
+/// Skip RFC 5424 structured data, "-" or any number of "[id key="value"]" elements, returning
+/// the message after it
+fn skip_structured_data(text: &str) -> &str {
+    if let Some(rest) = text.strip_prefix('-') {
+        return rest.strip_prefix(' ').unwrap_or(rest);
+    }
+    let mut rest = text;
+    while rest.starts_with('[') {
+        let mut quoted = false;
+        let mut escaped = false;
+        let end = rest.char_indices().find(|(_, c)| {
+            if escaped {
+                escaped = false;
+            } else if *c == '\\' {
+                escaped = true;
+            } else if *c == '"' {
+                quoted = !quoted;
+            } else if *c == ']' && !quoted {
+                return true;
+            }
+            false
+        });
+        match end {
+            Some((end, _)) => rest = &rest[end + 1..],
+            None => return "",
+        }
+    }
+    rest.strip_prefix(' ').unwrap_or(rest)
+}
+
+/// Parse an RFC 3164 timestamp, which is local time without a year
+///
+/// The current year is assumed unless that puts the timestamp more than a day in the future, as
+/// happens for messages from late December read in January.
+fn parse_bsd_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    let now = Local::now();
+    [now.year(), now.year() - 1].into_iter().find_map(|year| {
+        let naive =
+            NaiveDateTime::parse_from_str(&format!("{} {}", year, text), "%Y %b %e %H:%M:%S")
+                .ok()?;
+        let local = Local.from_local_datetime(&naive).earliest()?;
+        Some(local.with_timezone(&Utc)).filter(|ts| *ts <= Utc::now() + Duration::days(1))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metrics::Metrics, sources::record_channel};
+
+    async fn frames(mut input: &[u8]) -> Vec<String> {
+        let mut frames = vec![];
+        let mut frame = vec![];
+        while read_frame(&mut input, &mut frame).await.unwrap() > 0 {
+            frames.push(String::from_utf8_lossy(&frame).into_owned());
+        }
+        frames
+    }
+
+    fn bsd(at: DateTime<Local>) -> String {
+        at.format("%b %e %H:%M:%S").to_string()
+    }
+
+    #[test]
+    fn parses_priority_bounds() {
+        assert_eq!(parse_priority("<0>msg"), Some((0, "msg")));
+        assert_eq!(parse_priority("<191>msg"), Some((191, "msg")));
+        assert_eq!(parse_priority("<192>msg"), None);
+        assert_eq!(parse_priority("<>msg"), None);
+        assert_eq!(parse_priority("<1a>msg"), None);
+        assert_eq!(parse_priority("<0001>msg"), None);
+        assert_eq!(parse_priority("13>msg"), None);
+    }
+
+    #[test]
+    fn parses_rfc5424_without_structured_data() {
+        let message = SyslogMessage::parse(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog 1234 ID47 - started\n",
+        );
+        assert_eq!(message.facility, Some(20));
+        assert_eq!(message.severity, Some(5));
+        assert_eq!(
+            message.timestamp.map(|ts| ts.to_rfc3339()),
+            Some("2003-10-11T22:14:15.003+00:00".to_string())
+        );
+        assert_eq!(message.hostname, Some("mymachine.example.com"));
+        assert_eq!(message.app, Some("evntslog"));
+        assert_eq!(message.procid, Some("1234"));
+        assert_eq!(message.message, "started");
+    }
+
+    #[test]
+    fn parses_rfc5424_nil_values() {
+        let message = SyslogMessage::parse("<14>1 - - - - - - just the message");
+        assert_eq!(message.timestamp, None);
+        assert_eq!(message.hostname, None);
+        assert_eq!(message.app, None);
+        assert_eq!(message.procid, None);
+        assert_eq!(message.message, "just the message");
+    }
+
+    #[test]
+    fn skips_nested_and_escaped_structured_data() {
+        let message = SyslogMessage::parse(
+            r#"<165>1 2003-10-11T22:14:15Z host app - ID47 [exampleSDID@32473 iut="3" eventSource="App]lication" note="a \"quoted\] \\ value"][examplePriority@32473 class="high"] message [not sd]"#,
+        );
+        assert_eq!(message.message, "message [not sd]");
+    }
+
+    #[test]
+    fn strips_the_byte_order_mark() {
+        let message = SyslogMessage::parse(
+            "<165>1 2003-10-11T22:14:15Z host app - - - \u{feff}unicode message",
+        );
+        assert_eq!(message.message, "unicode message");
+    }
+
+    #[test]
+    fn parses_rfc3164_with_pid() {
+        let at = Local::now() - Duration::hours(1);
+        let text = format!("<34>{} mymachine su[230]: 'su root' failed", bsd(at));
+        let message = SyslogMessage::parse(&text);
+        assert_eq!(message.facility, Some(4));
+        assert_eq!(message.severity, Some(2));
+        assert!(message.timestamp.is_some());
+        assert_eq!(message.hostname, Some("mymachine"));
+        assert_eq!(message.app, Some("su"));
+        assert_eq!(message.procid, Some("230"));
+        assert_eq!(message.message, "'su root' failed");
+    }
+
+    #[test]
+    fn parses_rfc3164_without_pid() {
+        let at = Local::now() - Duration::hours(1);
+        let text = format!("<13>{} host cron: job finished", bsd(at));
+        let message = SyslogMessage::parse(&text);
+        assert_eq!(message.hostname, Some("host"));
+        assert_eq!(message.app, Some("cron"));
+        assert_eq!(message.procid, None);
+        assert_eq!(message.message, "job finished");
+    }
+
+    #[test]
+    fn parses_rfc3164_without_tag() {
+        let at = Local::now() - Duration::hours(1);
+        let text = format!("<13>{} host something happened: details", bsd(at));
+        let message = SyslogMessage::parse(&text);
+        assert_eq!(message.hostname, Some("host"));
+        assert_eq!(message.app, None);
+        assert_eq!(message.message, "something happened: details");
+    }
+
+    #[test]
+    fn keeps_text_without_priority_whole() {
+        let message = SyslogMessage::parse("plain text line\r\n");
+        assert_eq!(
+            message,
+            SyslogMessage {
+                message: "plain text line",
+                ..SyslogMessage::default()
+            }
+        );
+    }
+
+    #[test]
+    fn names_severity_and_facility() {
+        let record =
+            SyslogMessage::parse("<34>1 - host su - - - failed").into_record(Arc::from("test"), 0);
+        assert_eq!(
+            record.fields.get("severity").map(String::as_str),
+            Some("crit")
+        );
+        assert_eq!(
+            record.fields.get("facility").map(String::as_str),
+            Some("auth")
+        );
+        assert_eq!(
+            record.fields.get("hostname").map(String::as_str),
+            Some("host")
+        );
+    }
+
+    #[test]
+    fn bsd_timestamp_keeps_the_current_year_for_recent_times() {
+        let at = Local::now() - Duration::hours(1);
+        let parsed = parse_bsd_timestamp(&bsd(at)).unwrap();
+        assert_eq!(parsed.timestamp(), at.timestamp());
+    }
+
+    #[test]
+    fn bsd_timestamp_crosses_the_year_boundary() {
+        // Read shortly after new year, a December timestamp belongs to the year before, which is
+        // the same as a timestamp a few days ahead being taken from the previous year
+        let ahead = Local::now() + Duration::days(3);
+        let parsed = parse_bsd_timestamp(&bsd(ahead)).unwrap();
+        assert!(parsed <= Utc::now());
+        assert_eq!(parsed.with_timezone(&Local).year(), ahead.year() - 1);
+    }
+
+    #[test]
+    fn rejects_invalid_bsd_timestamps() {
+        assert_eq!(parse_bsd_timestamp("Foo  8 22:14:15"), None);
+        assert_eq!(parse_bsd_timestamp("Oct 32 22:14:15"), None);
+    }
+
+    #[tokio::test]
+    async fn reads_octet_counted_frames() {
+        let input = b"11 <13>1 first10 <13>1 next";
+        assert_eq!(frames(input).await, vec!["<13>1 first", "<13>1 next"]);
+    }
+
+    #[tokio::test]
+    async fn reads_newline_framed_messages() {
+        let input = b"<13>1 first\n<13>1 second\r\n<13>1 unterminated";
+        assert_eq!(
+            frames(input).await,
+            vec!["<13>1 first\n", "<13>1 second\r\n", "<13>1 unterminated"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_mixed_framing() {
+        let input = b"11 <13>1 first<13>1 line\n404 not found\n5 <13>x\n12345678 digits\n";
+        assert_eq!(
+            frames(input).await,
+            vec![
+                "<13>1 first",
+                "<13>1 line\n",
+                "404 not found\n",
+                "<13>x",
+                "\n",
+                "12345678 digits\n"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_invalid_lengths_as_lines() {
+        let input = b"12 apples\n99999 <13>1 longer than any message\n0 <13>1 empty\n";
+        assert_eq!(
+            frames(input).await,
+            vec![
+                "12 apples\n",
+                "99999 <13>1 longer than any message\n",
+                "0 <13>1 empty\n"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn splits_overlong_lines() {
+        let mut input = vec![b'a'; MAX_MESSAGE_LEN + 10];
+        input.push(b'\n');
+        let frames = frames(&input).await;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].len(), MAX_MESSAGE_LEN);
+        assert_eq!(frames[1].len(), 11);
+    }
+
+    #[tokio::test]
+    async fn drops_idle_datagram_peers() {
+        let metrics = Arc::new(Metrics::new(16));
+        let (writer, mut reader) = record_channel(metrics.clone());
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let timeout = std::time::Duration::from_millis(200);
+        let handle = tokio::spawn(receive_datagrams(socket, timeout, writer));
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender.send_to(b"<13>first", addr).await.unwrap();
+        let record = reader.recv().await.unwrap();
+        assert_eq!(&*record.source, "syslog udp 127.0.0.1");
+        assert_eq!(record.offset, 0);
+        assert!(metrics.source_states().contains_key(&record.source));
+
+        time::sleep(timeout * 3).await;
+        assert!(!metrics.source_states().contains_key(&record.source));
+        // A peer which comes back is a new source
+        sender.send_to(b"<13>again", addr).await.unwrap();
+        assert_eq!(reader.recv().await.unwrap().offset, 0);
+        assert!(metrics.source_states().contains_key(&record.source));
+        handle.abort();
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{
+    fs::{rename, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::groups::GroupSummary;
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Saved drain state, restored by replaying each template into a fresh drain
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    pub version: u32,
+    pub saved_at: DateTime<Utc>,
+    pub groups: Vec<SnapshotGroup>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct SnapshotGroup {
+    pub uid: String,
+    pub template: String,
+    pub count: usize,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl From<&GroupSummary> for SnapshotGroup {
+    fn from(group: &GroupSummary) -> Self {
+        Self {
+            uid: group.id.clone(),
+            template: group.template.clone(),
+            count: group.count,
+            first_seen: group.first_seen,
+            last_seen: group.last_seen,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Groups without any lines from the input, only seen in the baseline, are left out as
+    /// restoring them would count their replayed template as a line
+    pub(crate) fn new(groups: &[GroupSummary]) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            saved_at: Utc::now(),
+            groups: groups
+                .iter()
+                .filter(|group| group.count > 0)
+                .map(SnapshotGroup::from)
+                .collect(),
+        }
+    }
+
+    #[instrument(level = "trace")]
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let mut snapshot: Snapshot = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "unsupported state file version {} in {}",
+                snapshot.version,
+                path.display()
+            ));
+        }
+        // Older state files can hold empty groups, which have nothing to restore
+        snapshot.groups.retain(|group| group.count > 0);
+        debug!(groups = snapshot.groups.len(), "loaded snapshot");
+        Ok(snapshot)
+    }
+
+    /// Write the snapshot next to `path` and move it into place so a crash never leaves a
+    /// truncated state file behind
+    #[instrument(level = "trace", skip(self))]
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Error> {
+        let tmp = path.with_extension("tmp");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut out, self)?;
+        out.flush()?;
+        drop(out);
+        rename(&tmp, path)?;
+        debug!(groups = self.groups.len(), "saved snapshot");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use chrono::Duration;
+
+    use super::*;
+
+    fn group(id: &str, count: usize) -> GroupSummary {
+        let last_seen = DateTime::parse_from_rfc3339("2022-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        GroupSummary {
+            key: id.to_string(),
+            id: id.to_string(),
+            template: format!("template {}", id),
+            count,
+            first_seen: last_seen - Duration::minutes(5),
+            last_seen,
+            rate: 0.0,
+            activity: vec![],
+            parameters: vec![],
+            anomaly: None,
+            comparison: None,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("lyretail-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = temp_path("round-trip");
+        let groups = [group("1", 3), group("2", 1)];
+        Snapshot::new(&groups).save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.version, SNAPSHOT_VERSION);
+        assert_eq!(loaded.groups.len(), 2);
+        for (saved, group) in loaded.groups.iter().zip(&groups) {
+            assert_eq!(saved.uid, group.id);
+            assert_eq!(saved.template, group.template);
+            assert_eq!(saved.count, group.count);
+            assert_eq!(saved.first_seen, group.first_seen);
+            assert_eq!(saved.last_seen, group.last_seen);
+        }
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn empty_groups_are_not_saved() {
+        let snapshot = Snapshot::new(&[group("1", 2), group("2", 0)]);
+        assert_eq!(snapshot.groups.len(), 1);
+        assert_eq!(snapshot.groups[0].uid, "1");
+    }
+
+    #[test]
+    fn empty_groups_are_not_loaded() {
+        let path = temp_path("empty");
+        let mut snapshot = Snapshot::new(&[group("1", 2)]);
+        snapshot.groups.push(SnapshotGroup::from(&group("2", 0)));
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.groups.len(), 1);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = temp_path("version");
+        let mut snapshot = Snapshot::new(&[group("1", 2)]);
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use chrono::{DateTime, Utc};
+
+const SPARK_CHARS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Counts of events in fixed size time buckets over a rolling window
+///
+/// Buckets live in a ring indexed by bucket number, slots are zeroed as time moves past them
+/// so nothing needs to happen while no events arrive.
+#[derive(Clone, Debug)]
+struct RollingCounts {
+    resolution_secs: i64,
+    buckets: Vec<u32>,
+    // Bucket number of the most recent event
+    head: i64,
+}
+
+impl RollingCounts {
+    fn new(resolution_secs: i64, len: usize) -> Self {
+        Self {
+            resolution_secs,
+            buckets: vec![0; len],
+            head: 0,
+        }
+    }
+
+    fn bucket(&self, at: DateTime<Utc>) -> i64 {
+        at.timestamp().div_euclid(self.resolution_secs)
+    }
+
+    fn slot(&self, bucket: i64) -> usize {
+        bucket.rem_euclid(self.buckets.len() as i64) as usize
+    }
+
+    fn record(&mut self, at: DateTime<Utc>) {
+        let len = self.buckets.len() as i64;
+        let bucket = self.bucket(at);
+        if bucket > self.head {
+            // Clear the slots skipped over, all of them when the gap is longer than the window
+            for stale in (self.head + 1).max(bucket - len + 1)..=bucket {
+                let slot = self.slot(stale);
+                self.buckets[slot] = 0;
+            }
+            self.head = bucket;
+        } else if bucket <= self.head - len {
+            // Older than the window
+            return;
+        }
+        let slot = self.slot(bucket);
+        self.buckets[slot] = self.buckets[slot].saturating_add(1);
+    }
+
+    /// The last `n` buckets up to and including the one containing `now`, oldest first
+    fn series(&self, now: DateTime<Utc>, n: usize) -> Vec<u64> {
+        let len = self.buckets.len() as i64;
+        let end = self.bucket(now);
+        (0..n.min(self.buckets.len()) as i64)
+            .rev()
+            .map(|ago| {
+                let bucket = end - ago;
+                if bucket > self.head || bucket <= self.head - len {
+                    0
+                } else {
+                    u64::from(self.buckets[self.slot(bucket)])
+                }
+            })
+            .collect()
+    }
+}
+
+/// How often a group has been seen, per second for the last hour and per minute for the last day
+#[derive(Clone, Debug)]
+pub(crate) struct Timeline {
+    seconds: RollingCounts,
+    minutes: RollingCounts,
+}
+
+impl Timeline {
+    pub(crate) fn new() -> Self {
+        Self {
+            seconds: RollingCounts::new(1, 60 * 60),
+            minutes: RollingCounts::new(60, 24 * 60),
+        }
+    }
+
+    pub(crate) fn record(&mut self, at: DateTime<Utc>) {
+        self.seconds.record(at);
+        self.minutes.record(at);
+    }
+
+    /// Lines in each of the last `n` seconds, up to an hour, oldest first
+    pub(crate) fn per_second(&self, now: DateTime<Utc>, n: usize) -> Vec<u64> {
+        self.seconds.series(now, n)
+    }
+
+    /// Lines in each of the last `n` minutes, up to a day, oldest first
+    pub(crate) fn per_minute(&self, now: DateTime<Utc>, n: usize) -> Vec<u64> {
+        self.minutes.series(now, n)
+    }
+}
+
+/// Sum adjacent values of `series` down to at most `bins` values
+pub(crate) fn rebin(series: &[u64], bins: usize) -> Vec<u64> {
+    if bins == 0 || series.len() <= bins {
+        return series.to_vec();
+    }
+    let per_bin = series.len().div_ceil(bins);
+    series
+        .chunks(per_bin)
+        .map(|chunk| chunk.iter().sum())
+        .collect()
+}
+
+/// Render `series` as a line of block characters scaled to its largest value
+pub(crate) fn sparkline(series: &[u64]) -> String {
+    let max = series.iter().copied().max().unwrap_or(0);
+    series
+        .iter()
+        .map(|value| {
+            if max == 0 || *value == 0 {
+                SPARK_CHARS[0]
+            } else {
+                // Rounded up so any activity shows at least the lowest bar
+                let level = (value * (SPARK_CHARS.len() as u64 - 1)).div_ceil(max);
+                SPARK_CHARS[level as usize]
+            }
+        })
+        .collect()
+}
+
+/// Exponentially weighted lines per second, events fade out with a time constant of `window_secs`
+///
+/// Until the rate has been tracked for a few windows the weighted sum undercounts, so it is scaled
+/// up by the share of the window which has elapsed. This lets rates with different windows be
+/// compared from the start.
+#[derive(Clone, Debug)]
+pub(crate) struct DecayingRate {
+    window_secs: f64,
+    rate: f64,
+    started: DateTime<Utc>,
+    updated: DateTime<Utc>,
+}
+
+impl DecayingRate {
+    pub(crate) fn new(window_secs: f64, now: DateTime<Utc>) -> Self {
+        Self {
+            window_secs,
+            rate: 0.0,
+            started: now,
+            updated: now,
+        }
+    }
+
+    pub(crate) fn record(&mut self, at: DateTime<Utc>) {
+        self.rate = self.decayed(at) + 1.0 / self.window_secs;
+        self.updated = self.updated.max(at);
+    }
+
+    fn decayed(&self, now: DateTime<Utc>) -> f64 {
+        self.rate * (-secs_between(self.updated, now) / self.window_secs).exp()
+    }
+
+    /// Lines per second as of `now`
+    pub(crate) fn at(&self, now: DateTime<Utc>) -> f64 {
+        // Treat anything younger than a second as a second old so a burst doesn't divide by zero
+        let age = secs_between(self.started, now).max(1.0);
+        self.decayed(now) / (1.0 - (-age / self.window_secs).exp())
+    }
+}
+
+fn secs_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds().max(0) as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp(secs, 0)
+    }
+
+    #[test]
+    fn counts_events_per_bucket() {
+        let mut counts = RollingCounts::new(10, 6);
+        for secs in [1000, 1005, 1009, 1010, 1035] {
+            counts.record(at(secs));
+        }
+        assert_eq!(counts.series(at(1035), 4), vec![3, 1, 0, 1]);
+        // Buckets after the latest event are empty
+        assert_eq!(counts.series(at(1055), 3), vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn buckets_are_reused_as_the_window_wraps() {
+        let mut counts = RollingCounts::new(1, 4);
+        for secs in 0..10 {
+            counts.record(at(secs));
+            counts.record(at(secs));
+        }
+        counts.record(at(9));
+        assert_eq!(counts.series(at(9), 4), vec![2, 2, 2, 3]);
+        assert_eq!(counts.series(at(9), 10).len(), 4);
+    }
+
+    #[test]
+    fn old_buckets_expire() {
+        let mut counts = RollingCounts::new(1, 4);
+        counts.record(at(100));
+        counts.record(at(101));
+        // A gap longer than the window clears every slot
+        counts.record(at(110));
+        assert_eq!(counts.series(at(110), 4), vec![0, 0, 0, 1]);
+        // Events which fell out of the window are dropped, late ones inside it are counted
+        counts.record(at(106));
+        counts.record(at(108));
+        assert_eq!(counts.series(at(110), 4), vec![0, 1, 0, 1]);
+        // Nothing is reported once the window has moved past the last event
+        assert_eq!(counts.series(at(120), 4), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rebins_to_fewer_values() {
+        let series = [1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(rebin(&series, 3), vec![6, 15, 7]);
+        assert_eq!(rebin(&series, 1), vec![28]);
+        assert_eq!(rebin(&series, 7), series.to_vec());
+        assert_eq!(rebin(&series, 10), series.to_vec());
+        assert_eq!(rebin(&series, 0), series.to_vec());
+    }
+
+    #[test]
+    fn sparkline_scales_to_the_largest_value() {
+        assert_eq!(sparkline(&[0, 1, 4, 8]), " ▁▄█");
+        assert_eq!(sparkline(&[0, 0]), "  ");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn decaying_rate_converges_to_a_steady_rate() {
+        let start = at(0);
+        let mut rate = DecayingRate::new(60.0, start);
+        // Two lines a second for ten windows
+        for ms in (0..600_000).step_by(500) {
+            rate.record(start + Duration::milliseconds(ms));
+        }
+        let now = at(600);
+        assert!((rate.at(now) - 2.0).abs() < 0.05, "{}", rate.at(now));
+        // The same rate is reported straight away rather than ramping up over the window
+        let mut early = DecayingRate::new(60.0, start);
+        for secs in 0..10 {
+            early.record(at(secs));
+        }
+        assert!((early.at(at(10)) - 1.0).abs() < 0.1, "{}", early.at(at(10)));
+    }
+
+    #[test]
+    fn decaying_rate_fades_without_events() {
+        let mut rate = DecayingRate::new(60.0, at(0));
+        for secs in 0..600 {
+            rate.record(at(secs));
+        }
+        let steady = rate.at(at(600));
+        assert!(rate.at(at(660)) < steady / 2.0);
+        assert!(rate.at(at(600 + 5 * 60)) < 0.01);
+    }
+}
@@ -8,10 +8,7 @@
 // Server Side Public License along with this program.
 // If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
 
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
-};
+use std::{path::Path, sync::Arc};
 
 use crossterm::event::{Event, KeyCode, KeyModifiers};
 use drain_flow::log_group::LogGroup;
@@ -19,82 +16,237 @@ use itertools::Itertools;
 use tracing::{debug, info, instrument, warn};
 use tui::{
     backend::Backend,
-    layout::{Constraint, Layout},
+    layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Cell, Row, Table, TableState},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
     Frame,
 };
 
-use super::UiState;
-use crate::app::LyreTail;
+use super::{search::Search, UiState};
+use crate::{
+    anomaly::AnomalyKind,
+    app::LyreTail,
+    compare::Change,
+    export::{export_groups, ExportFormat},
+    groups::{find_group, GroupSummary, SortOrder},
+    timeline::{rebin, sparkline},
+};
+
+// Width of the activity sparkline, each character covers a few seconds of the last minute
+const SPARK_BINS: usize = 20;
 
 #[derive(Clone, Debug)]
 pub(crate) struct BaseTable {
-    row_count: Arc<AtomicUsize>,
+    // Keys of the rows shown by the last render, in display order
+    keys: Vec<String>,
+    // Key of the selected group, which the highlight follows as the table is re-sorted
+    selected: Option<String>,
     state: TableState,
     app: Arc<LyreTail>,
+    message: Option<String>,
+    search: Search,
+    order: SortOrder,
+    // Whether to hide groups which haven't changed from the baseline
+    changed_only: bool,
 }
 
 impl<'a> BaseTable {
     pub(crate) fn new(app: Arc<LyreTail>) -> Self {
         Self {
-            row_count: Arc::new(AtomicUsize::new(0)),
+            keys: vec![],
+            selected: None,
             state: TableState::default(),
             app: app.clone(),
+            message: None,
+            search: Search::default(),
+            order: SortOrder::Count,
+            changed_only: false,
         }
     }
 
+    /// Groups shown in the table, in display order
+    fn rows(&self) -> Vec<GroupSummary> {
+        self.app
+            .summaries()
+            .into_iter()
+            .filter(|group| self.search.matches(group))
+            .filter(|group| !self.changed_only || group.has_changed())
+            .sorted_by(|a, b| self.order.compare(a, b))
+            .collect()
+    }
+
     #[instrument(skip(self, f))]
-    pub(crate) fn do_render<B: Backend>(&mut self, f: &mut Frame<B>) {
+    pub(crate) fn do_render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
         debug!("starting render_ui");
         let rects = Layout::default()
-            .constraints([Constraint::Percentage(100)].as_ref())
+            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
             .margin(5)
-            .split(f.size());
-        let header_cells = ["ID", "Event", "Quantity Seen"]
-            .iter()
-            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Red)));
+            .split(area);
+        let comparing = self.app.get_groups_ref().read().is_comparing();
+        let sorted_column = match self.order {
+            SortOrder::Template => Some(1),
+            SortOrder::Count => Some(2),
+            SortOrder::Rate => Some(3),
+            SortOrder::Change => Some(6),
+            SortOrder::FirstSeen | SortOrder::LastSeen => None,
+        };
+        let mut headers = vec!["ID", "Event", "Quantity Seen", "Rate/s", "Last minute"];
+        if comparing {
+            headers.extend(["Baseline", "Change"]);
+        }
+        let columns = headers.len() as u16;
+        let header_cells = headers.iter().enumerate().map(|(i, h)| {
+            let label = if sorted_column == Some(i) {
+                format!("{} v", h)
+            } else {
+                h.to_string()
+            };
+            Cell::from(label).style(Style::default().fg(Color::Red))
+        });
         let selected_style = Style::default().add_modifier(Modifier::REVERSED);
         let normal_style = Style::default().bg(Color::Blue);
         let header = Row::new(header_cells)
             .style(normal_style)
             .height(1)
             .bottom_margin(1);
-        let rows = self
-            .app
-            .get_drain_ref()
-            .read()
-            .iter_groups()
+        let groups = self.rows();
+        self.keys = groups.iter().map(|group| group.key.clone()).collect();
+        let new = groups
             .iter()
-            .flatten()
-            .sorted_by(|a, b| Ord::cmp(&b.len(), &a.len()))
-            .map(|lg| {
-                let cells = vec![
-                    Cell::from(lg.event().uid.serialize()),
-                    Cell::from(lg.event().to_string()),
-                    Cell::from(lg.len().to_string()),
+            .filter(|group| group.anomaly == Some(AnomalyKind::NewTemplate))
+            .count();
+        let spiking = groups
+            .iter()
+            .filter(|group| group.anomaly == Some(AnomalyKind::RateSpike))
+            .count();
+        let changed = groups.iter().filter(|group| group.has_changed()).count();
+        let rows = groups
+            .into_iter()
+            .map(|group| {
+                let style = match group.anomaly {
+                    Some(AnomalyKind::RateSpike) => {
+                        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+                    },
+                    Some(AnomalyKind::NewTemplate) => Style::default().fg(Color::Yellow),
+                    None => Style::default(),
+                };
+                let mut cells = vec![
+                    Cell::from(group.id),
+                    Cell::from(group.template),
+                    Cell::from(group.count.to_string()),
+                    Cell::from(format!("{:.2}", group.rate)),
+                    Cell::from(sparkline(&rebin(&group.activity, SPARK_BINS)))
+                        .style(Style::default().fg(Color::Cyan)),
                 ];
-                Row::new(cells).height(1).bottom_margin(1)
+                if let Some(comparison) = group.comparison {
+                    let change_style = match comparison.change {
+                        Change::Added | Change::Increased => Style::default().fg(Color::Green),
+                        Change::Removed | Change::Decreased => Style::default().fg(Color::Magenta),
+                        Change::Unchanged => Style::default(),
+                    };
+                    cells.push(Cell::from(comparison.baseline_count.to_string()));
+                    cells.push(Cell::from(comparison.describe()).style(change_style));
+                }
+                Row::new(cells).style(style).height(1).bottom_margin(1)
             })
             .collect::<Vec<Row>>();
-        self.row_count.store(rows.len(), Ordering::SeqCst);
+        // Find where the selected group moved to, if it was filtered out select the row which took
+        // its place instead
+        let position = self
+            .selected
+            .as_ref()
+            .and_then(|key| self.keys.iter().position(|k| k == key));
+        match position {
+            Some(idx) => self.state.select(Some(idx)),
+            None if self.selected.is_some() => {
+                let idx = self
+                    .state
+                    .selected()
+                    .unwrap_or(0)
+                    .min(self.keys.len().saturating_sub(1));
+                self.select(Some(idx));
+            },
+            None => self.state.select(None),
+        }
+        if self.search.is_active() {
+            let prompt = Paragraph::new(self.search.prompt(rows.len()));
+            f.render_widget(prompt, rects[1]);
+        }
+        let mut title = format!("LogGroups by {}", self.order.label());
+        if new > 0 || spiking > 0 {
+            title.push_str(&format!(
+                ", {} new (yellow), {} spiking (red)",
+                new, spiking
+            ));
+        }
+        if comparing {
+            title.push_str(&format!(", {} changed from baseline", changed));
+            if self.changed_only {
+                title.push_str(" (only changed shown)");
+            }
+        }
+        if let Some(message) = &self.message {
+            title.push_str(&format!(" {}", message));
+        }
+        // Fixed width columns are sized up front, the layout solver would otherwise shave columns off
+        // the sparkline and hide its most recent bars. Two border columns, the highlight symbol and
+        // the spacing between the columns are taken out first
+        let inner = rects[0].width.saturating_sub(2 + 3 + columns - 1);
+        let id_width = inner * 15 / 100;
+        let mut numbers = vec![13, 8, SPARK_BINS as u16];
+        if comparing {
+            numbers.extend([10, 10]);
+        }
+        let event_width = inner.saturating_sub(id_width + numbers.iter().sum::<u16>());
+        let widths = [
+            Constraint::Length(id_width),
+            Constraint::Length(event_width),
+        ]
+        .into_iter()
+        .chain(numbers.into_iter().map(Constraint::Length))
+        .collect::<Vec<_>>();
         let t = Table::new(rows)
             .header(header)
-            .block(Block::default().borders(Borders::ALL).title("LogGroups"))
+            .block(Block::default().borders(Borders::ALL).title(title))
             .highlight_style(selected_style)
             .highlight_symbol(">> ")
-            .widths(&[
-                Constraint::Percentage(20),
-                Constraint::Percentage(70),
-                Constraint::Percentage(10),
-            ]);
+            .widths(&widths);
         debug!("finished building table");
         f.render_stateful_widget(t, rects[0], &mut self.state);
     }
 
+    /// Select the row at `idx` of the last render, remembering which group it shows
+    fn select(&mut self, idx: Option<usize>) {
+        let idx = idx.filter(|idx| *idx < self.keys.len());
+        self.selected = idx.map(|idx| self.keys[idx].clone());
+        self.state.select(idx);
+    }
+
     pub(crate) fn handle_events(&mut self, event: Event) -> UiState {
-        let rows = self.row_count.load(Ordering::SeqCst);
+        let rows = self.keys.len();
         if let Event::Key(key) = event {
+            // While typing a search keys go to the query, except for navigation and ctrl-c
+            if self.search.is_editing() && !key.modifiers.contains(KeyModifiers::CONTROL) {
+                match key.code {
+                    KeyCode::Char(c) => {
+                        self.search.push(c);
+                        return UiState::Base;
+                    },
+                    KeyCode::Backspace => {
+                        self.search.pop();
+                        return UiState::Base;
+                    },
+                    KeyCode::Enter => {
+                        self.search.finish();
+                        return UiState::Base;
+                    },
+                    KeyCode::Esc => {
+                        self.search.clear();
+                        return UiState::Base;
+                    },
+                    _ => {},
+                }
+            }
             match key.code {
                 KeyCode::Up => {
                     debug!("key up handler");
@@ -104,11 +256,11 @@ impl<'a> BaseTable {
                         // When navigating a list up is down and we stop at 0
                         if selected > 0 {
                             info!("setting selection to {}", selected - 1);
-                            self.state.select(Some(selected - 1));
+                            self.select(Some(selected - 1));
                         }
                     } else {
                         info!("nothing selected key up");
-                        self.state.select(Some(0));
+                        self.select(Some(0));
                     }
                     return UiState::Base;
                 },
@@ -117,18 +269,24 @@ impl<'a> BaseTable {
                     let selected = self.state.selected();
                     if let Some(selected) = selected {
                         info!(%selected, %rows, "change selection");
-                        if selected < rows {
+                        if selected + 1 < rows {
                             info!("setting selection to {}", selected + 1);
-                            self.state.select(Some(selected + 1));
+                            self.select(Some(selected + 1));
                         }
                     } else {
-                        info!("nothing selected, setting {}", rows - 1);
-                        self.state.select(Some(rows - 1));
+                        info!("nothing selected, setting {}", rows.saturating_sub(1));
+                        self.select(rows.checked_sub(1));
                     }
 
                     return UiState::Base;
                 },
                 KeyCode::Esc => {
+                    // Esc clears an applied search before it exits
+                    if self.search.is_active() {
+                        debug!("key esc, clearing search");
+                        self.search.clear();
+                        return UiState::Base;
+                    }
                     debug!("key esc");
                     return UiState::Exiting;
                 },
@@ -137,13 +295,34 @@ impl<'a> BaseTable {
                     if (c == 'c' && key.modifiers.contains(KeyModifiers::CONTROL)) || c == 'q' {
                         debug!("key ctrl-c");
                         return UiState::Exiting;
+                    } else if c == 'e' {
+                        self.export();
+                        return UiState::Base;
+                    } else if c == 's' {
+                        self.order = self.order.next();
+                        // Sorting by change only means something against a baseline
+                        if self.order == SortOrder::Change
+                            && !self.app.get_groups_ref().read().is_comparing()
+                        {
+                            self.order = self.order.next();
+                        }
+                        return UiState::Base;
+                    } else if c == 'c' {
+                        self.changed_only =
+                            !self.changed_only && self.app.get_groups_ref().read().is_comparing();
+                        return UiState::Base;
+                    } else if c == '/' {
+                        self.search.start();
+                        return UiState::Base;
+                    } else if c == 'n' || c == 'N' {
+                        self.jump(c == 'n', rows);
+                        return UiState::Base;
                     } else {
                         return UiState::Base;
                     }
                 },
                 KeyCode::Enter => {
-                    if let Some(selected) = self.state.selected() {
-                        let lg = self.get_selected(selected);
+                    if let Some(lg) = self.get_selected() {
                         return UiState::LogGroup(Arc::new(lg));
                     } else {
                         return UiState::Base;
@@ -158,17 +337,45 @@ impl<'a> BaseTable {
         UiState::Base
     }
 
-    fn get_selected(&self, idx: usize) -> LogGroup {
-        self.app
-            .get_drain_ref()
-            .read()
-            .iter_groups()
-            .iter()
-            .flatten()
-            .sorted_by(|a, b| Ord::cmp(&b.len(), &a.len()))
-            .nth(idx)
-            .expect("idx is based on selected, should exist")
-            .clone()
-            .to_owned()
+    /// Move the selection to the next or previous of the matching rows, wrapping at either end
+    fn jump(&mut self, forward: bool, rows: usize) {
+        if rows == 0 {
+            return;
+        }
+        let next = match self.state.selected() {
+            Some(selected) if forward => (selected + 1) % rows,
+            Some(selected) => (selected + rows - 1) % rows,
+            None if forward => 0,
+            None => rows - 1,
+        };
+        self.select(Some(next));
+    }
+
+    /// Export all groups using the configured format, stdout belongs to the ui so exports which
+    /// would go there are written to the default file instead
+    fn export(&mut self) {
+        let (format, path) = {
+            let args = self.app.args.lock();
+            let format = args.export_format.unwrap_or(ExportFormat::Json);
+            let path = match &args.export_file {
+                Some(path) if path != Path::new("-") => path.clone(),
+                _ => format.default_path(),
+            };
+            (format, path)
+        };
+        self.message = match export_groups(format, &self.app.summaries(), Some(&path)) {
+            Ok(_) => Some(format!("exported to {}", path.display())),
+            Err(e) => {
+                warn!(%e, "export failed");
+                Some(format!("export failed: {}", e))
+            },
+        };
+    }
+
+    /// The selected group as it is now, looked up by key so it's the group that was highlighted
+    /// even if the ranking changed since the last render
+    fn get_selected(&self) -> Option<LogGroup> {
+        let key = self.selected.as_ref()?;
+        find_group(&self.app.get_drain_ref().read(), key)
     }
 }
@@ -10,52 +10,235 @@
 
 use std::sync::Arc;
 
+use chrono::Utc;
 use crossterm::event::{Event, KeyCode, KeyModifiers};
 use drain_flow::log_group::LogGroup;
+use itertools::Itertools;
 use tracing::debug;
 use tui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Paragraph, Wrap},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Sparkline, Wrap},
     Frame,
 };
 
 use super::UiState;
+use crate::{
+    app::LyreTail,
+    groups::{find_group, group_key, WILDCARD},
+    timeline::rebin,
+};
+
+// Rows moved by PageUp and PageDown
+const PAGE: usize = 10;
 
 #[derive(Debug, Clone)]
 pub(crate) struct LogGroupTab {
+    app: Arc<LyreTail>,
     lg: Arc<LogGroup>,
+    samples: ListState,
 }
 
 impl LogGroupTab {
-    pub(crate) fn new(lg: Arc<LogGroup>) -> Self {
-        Self { lg }
+    pub(crate) fn new(app: Arc<LyreTail>, lg: Arc<LogGroup>) -> Self {
+        let mut samples = ListState::default();
+        samples.select(Some(0));
+        Self { app, lg, samples }
+    }
+
+    /// Template with its wildcard tokens highlighted
+    fn template_spans(template: &str) -> Spans<'static> {
+        let mut spans = vec![Span::raw("Template: ")];
+        for (i, token) in template.split(' ').enumerate() {
+            if i > 0 {
+                spans.push(Span::raw(" "));
+            }
+            if token == WILDCARD {
+                spans.push(Span::styled(
+                    token.to_string(),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            } else {
+                spans.push(Span::raw(token.to_string()));
+            }
+        }
+        Spans::from(spans)
     }
 
-    pub(crate) fn do_render<B: Backend>(&self, f: &mut Frame<B>) {
+    pub(crate) fn do_render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
         let rects = Layout::default()
-            .constraints([Constraint::Percentage(100)].as_ref())
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Percentage(40),
+                    Constraint::Length(8),
+                    Constraint::Min(0),
+                ]
+                .as_ref(),
+            )
             .margin(5)
-            .split(f.size());
-        let lines = vec![Spans::from(vec![Span::raw(format!(
-            "Log Group: {}",
-            self.lg.get_id()
-        ))])];
+            .split(area);
+        let key = group_key(&self.lg);
+        // The template gains wildcards as more lines join the group, so show the live one
+        let template = find_group(&self.app.get_drain_ref().read(), &key)
+            .map_or_else(|| self.lg.event().to_string(), |lg| lg.event().to_string());
+        let groups = self.app.get_groups_ref();
+        let groups = groups.read();
+        let mut lines = vec![Self::template_spans(template.trim_end())];
+        let mut samples = vec![];
+        let mut hour = vec![];
+        let mut day = vec![];
+        // One bar per column of the charts
+        let width = rects[1].width as usize;
+        if let Some(info) = groups.get(&key) {
+            let now = Utc::now();
+            hour = rebin(&info.timeline.per_second(now, 60 * 60), width);
+            day = rebin(&info.timeline.per_minute(now, 24 * 60), width);
+            lines.push(Spans::from(Span::raw(format!("Log Group: {}", info.id))));
+            lines.push(Spans::from(Span::raw(format!("Count: {}", info.count))));
+            if let Some(anomaly) = info.anomaly(groups.anomaly_config(), now) {
+                lines.push(Spans::from(Span::styled(
+                    format!("Anomaly: {}", anomaly),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                )));
+            }
+            if let Some(comparison) = groups.comparison(&key) {
+                lines.push(Spans::from(Span::raw(format!(
+                    "Baseline: {} lines, {:.3}% of the baseline against {:.3}% of the input, {}",
+                    comparison.baseline_count,
+                    comparison.baseline_share * 100.0,
+                    comparison.share * 100.0,
+                    comparison.describe()
+                ))));
+            }
+            lines.push(Spans::from(Span::raw(format!(
+                "Rate: {:.2}/s over the last minute, {:.2}/s average",
+                info.recent_rate(now),
+                info.average_rate()
+            ))));
+            lines.push(Spans::from(Span::raw(format!(
+                "First seen: {}",
+                info.first_seen.to_rfc3339()
+            ))));
+            lines.push(Spans::from(Span::raw(format!(
+                "Last seen: {}",
+                info.last_seen.to_rfc3339()
+            ))));
+            if let Some((source, offset)) = &info.last_location {
+                lines.push(Spans::from(Span::raw(format!(
+                    "Last line: {} at {}",
+                    source, offset
+                ))));
+            }
+            lines.push(Spans::from(Span::raw("Sources:")));
+            for (source, count) in &info.sources {
+                lines.push(Spans::from(Span::raw(format!("  {}: {}", source, count))));
+            }
+            let parameters = info.params.summaries(&template);
+            if !parameters.is_empty() {
+                lines.push(Spans::from(Span::raw("Wildcard values:")));
+                for param in parameters {
+                    let values = param
+                        .values
+                        .iter()
+                        .map(|v| format!("{} ({})", v.value, v.count))
+                        .join(", ");
+                    lines.push(Spans::from(Span::raw(format!(
+                        "  token {}: {}",
+                        param.position, values
+                    ))));
+                }
+            }
+            if !info.last_fields.is_empty() {
+                lines.push(Spans::from(Span::raw("Fields of the latest line:")));
+                for (key, value) in &info.last_fields {
+                    lines.push(Spans::from(Span::raw(format!("  {}: {}", key, value))));
+                }
+            }
+            samples = info
+                .samples
+                .iter()
+                .map(|sample| {
+                    ListItem::new(Spans::from(vec![
+                        Span::styled(
+                            format!("{} {} ", sample.at.to_rfc3339(), sample.source),
+                            Style::default().fg(Color::DarkGray),
+                        ),
+                        Span::raw(sample.line.clone()),
+                    ]))
+                })
+                .collect();
+        } else {
+            lines.push(Spans::from(Span::raw(format!(
+                "Log Group: {}",
+                self.lg.get_id()
+            ))));
+        }
         let para = Paragraph::new(lines)
             .block(Block::default().title("Log Group"))
-            .alignment(Alignment::Center)
+            .alignment(Alignment::Left)
             .wrap(Wrap { trim: true });
         f.render_widget(para, rects[0]);
+
+        let charts = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(rects[1]);
+        for (rect, data, span) in [(charts[0], &hour, "hour"), (charts[1], &day, "day")] {
+            let title = format!(
+                "Last {}, peak {} lines per bar",
+                span,
+                data.iter().max().unwrap_or(&0)
+            );
+            let chart = Sparkline::default()
+                .block(Block::default().borders(Borders::TOP).title(title))
+                .style(Style::default().fg(Color::Cyan))
+                .data(data);
+            f.render_widget(chart, rect);
+        }
+
+        // Keep the selection in range as old samples are dropped
+        match self.samples.selected() {
+            Some(idx) if idx >= samples.len() => {
+                self.samples.select(Some(samples.len().saturating_sub(1)))
+            },
+            _ => {},
+        }
+        let title = format!("Recent lines ({}), newest first", samples.len());
+        let list = List::new(samples)
+            .block(Block::default().borders(Borders::TOP).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, rects[2], &mut self.samples);
+    }
+
+    /// Move the sample selection by delta rows, the render clamps it to the last sample
+    fn scroll(&mut self, delta: isize) {
+        let current = self.samples.selected().unwrap_or(0);
+        let next = if delta < 0 {
+            current.saturating_sub(delta.unsigned_abs())
+        } else {
+            current.saturating_add(delta as usize)
+        };
+        self.samples.select(Some(next));
     }
 
-    pub(crate) fn handle_events(&self, event: Event) -> UiState {
+    pub(crate) fn handle_events(&mut self, event: Event) -> UiState {
         let current = UiState::LogGroup(self.lg.clone());
         if let Event::Key(key) = event {
             match key.code {
                 KeyCode::Esc => {
                     return UiState::Base;
                 },
+                KeyCode::Up => self.scroll(-1),
+                KeyCode::Down => self.scroll(1),
+                KeyCode::PageUp => self.scroll(-(PAGE as isize)),
+                KeyCode::PageDown => self.scroll(PAGE as isize),
+                KeyCode::Home => self.samples.select(Some(0)),
+                KeyCode::End => self.samples.select(Some(usize::MAX)),
                 KeyCode::Char(c) => {
                     // Ctrl-C, q and Esc all trigger exit
                     if (c == 'c' && key.modifiers.contains(KeyModifiers::CONTROL)) || c == 'q' {
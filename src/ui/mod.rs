@@ -9,40 +9,47 @@
 // If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
 
 use std::{
-    io::{stdout, Stdout},
+    fs::OpenOptions,
+    io::{stdin, stdout, Stdout},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use chrono::Duration;
 use crossterm::{
     event::{self, Event},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    tty::IsTty,
 };
 use drain_flow::log_group::LogGroup;
 use tracing::{debug, instrument, warn};
 use tui::{
     backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Layout, Rect},
     Frame,
     Terminal,
 };
 
-use self::{base::BaseTable, log_group::LogGroupTab};
+use self::{base::BaseTable, log_group::LogGroupTab, status::StatusBar};
 use crate::app::LyreTail;
 
 mod base;
 mod log_group;
+mod search;
+mod status;
 
 pub(crate) struct Ui {
+    app: Arc<LyreTail>,
     base: BaseTable,
     stopping: Arc<AtomicBool>,
     state: UiState,
     terminal: Terminal<CrosstermBackend<Stdout>>,
-    log_group: Option<Arc<LogGroup>>,
+    log_group: Option<LogGroupTab>,
+    status: StatusBar,
 }
 
 #[derive(Clone)]
@@ -60,6 +67,22 @@ pub(crate) trait LyreUIWidget<B: Backend> {
 impl<'a> Ui {
     #[instrument(level = "trace", skip_all)]
     pub fn new<'b>(app: Arc<LyreTail>) -> Result<Self, Error> {
+        if !stdout().is_tty() {
+            return Err(anyhow!(
+                "stdout is not a terminal, the interactive ui cannot be drawn"
+            ));
+        }
+        // When logs are piped in stdin belongs to the source, crossterm falls back to reading
+        // keyboard events from the controlling terminal so check there is one before starting
+        if !stdin().is_tty() {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .map_err(|e| {
+                    anyhow!("stdin is not a terminal and /dev/tty is unavailable: {}", e)
+                })?;
+        }
         // setup terminal
         let mut stdout = stdout();
         enable_raw_mode()?;
@@ -70,6 +93,8 @@ impl<'a> Ui {
             state: UiState::Base,
             stopping: Arc::new(AtomicBool::new(false)),
             base: BaseTable::new(app.clone()),
+            status: StatusBar::new(app.clone()),
+            app,
             terminal,
             log_group: None,
         })
@@ -90,7 +115,12 @@ impl<'a> Ui {
             }
             self.state = match &self.state {
                 UiState::Base => {
-                    self.terminal.draw(|f| self.base.do_render(f))?;
+                    self.log_group = None;
+                    self.terminal.draw(|f| {
+                        let (view, status) = split_status(f.size());
+                        self.base.do_render(f, view);
+                        self.status.do_render(f, status);
+                    })?;
                     if crossterm::event::poll(Duration::milliseconds(10).to_std()?)? {
                         let event = event::read()?;
                         self.base.handle_events(event)
@@ -99,9 +129,16 @@ impl<'a> Ui {
                     }
                 },
                 UiState::LogGroup(log_group) => {
-                    self.log_group = Some(log_group.clone());
-                    let lg_view = LogGroupTab::new(log_group.clone());
-                    self.terminal.draw(|f| lg_view.do_render(f))?;
+                    // Kept across frames so the sample list holds its scroll position
+                    let lg_view = self.log_group.get_or_insert_with(|| {
+                        LogGroupTab::new(self.app.clone(), log_group.clone())
+                    });
+                    let status_bar = &mut self.status;
+                    self.terminal.draw(|f| {
+                        let (view, status) = split_status(f.size());
+                        lg_view.do_render(f, view);
+                        status_bar.do_render(f, status);
+                    })?;
                     if crossterm::event::poll(Duration::milliseconds(10).to_std()?)? {
                         let event = event::read()?;
                         lg_view.handle_events(event)
@@ -122,3 +159,11 @@ impl<'a> Ui {
         Ok(())
     }
 }
+
+/// Split the screen into the area for the current view and the status bar's line below it
+fn split_status(area: Rect) -> (Rect, Rect) {
+    let rects = Layout::default()
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(area);
+    (rects[0], rects[1])
+}
@@ -0,0 +1,95 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use regex::{Regex, RegexBuilder};
+
+use crate::groups::GroupSummary;
+
+/// Filter for the group table, typed after pressing `/`
+///
+/// The query is used as a case insensitive regex over each group's template and id, falling back
+/// to a plain substring match while it isn't a valid regex, e.g. half way through typing `(`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Search {
+    query: String,
+    editing: bool,
+    pattern: Option<Regex>,
+}
+
+impl Search {
+    pub(crate) fn start(&mut self) {
+        self.editing = true;
+    }
+
+    /// Stop editing, keeping the filter applied
+    pub(crate) fn finish(&mut self) {
+        self.editing = false;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Whether the prompt should be shown, either being typed or filtering rows
+    pub(crate) fn is_active(&self) -> bool {
+        self.editing || !self.query.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.compile();
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.query.pop();
+        self.compile();
+    }
+
+    fn compile(&mut self) {
+        self.pattern = RegexBuilder::new(&self.query)
+            .case_insensitive(true)
+            .build()
+            .ok();
+    }
+
+    pub(crate) fn matches(&self, group: &GroupSummary) -> bool {
+        if self.query.is_empty() {
+            return true;
+        }
+        match &self.pattern {
+            Some(pattern) => pattern.is_match(&group.template) || pattern.is_match(&group.id),
+            None => {
+                let query = self.query.to_lowercase();
+                group.template.to_lowercase().contains(&query)
+                    || group.id.to_lowercase().contains(&query)
+            },
+        }
+    }
+
+    /// Text of the search line shown under the table
+    pub(crate) fn prompt(&self, matches: usize) -> String {
+        let mut prompt = format!("/{}", self.query);
+        if self.editing {
+            prompt.push('_');
+        }
+        if !self.query.is_empty() && self.pattern.is_none() {
+            prompt.push_str(" (not a valid regex, matching text)");
+        }
+        prompt.push_str(&format!(
+            " - {} matching, n/N next/previous, esc clears",
+            matches
+        ));
+        prompt
+    }
+}
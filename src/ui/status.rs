@@ -0,0 +1,98 @@
+// Copyright Nicholas Harring. All rights reserved.
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the Server Side Public License, version 1, as published by MongoDB, Inc.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+// See the Server Side Public License for more details. You should have received a copy of the
+// Server Side Public License along with this program.
+// If not, see <http://www.mongodb.com/licensing/server-side-public-license>.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Paragraph,
+    Frame,
+};
+
+use crate::{app::LyreTail, metrics::SourceState};
+
+// How often the lines per second figure is recalculated
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Line along the bottom of the ui showing whether lyretail is still receiving data
+#[derive(Debug, Clone)]
+pub(crate) struct StatusBar {
+    app: Arc<LyreTail>,
+    sampled_at: Instant,
+    sampled: u64,
+    rate: f64,
+}
+
+impl StatusBar {
+    pub(crate) fn new(app: Arc<LyreTail>) -> Self {
+        Self {
+            app,
+            sampled_at: Instant::now(),
+            sampled: 0,
+            rate: 0.0,
+        }
+    }
+
+    pub(crate) fn do_render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let metrics = self.app.get_metrics_ref();
+        let processed = metrics.processed();
+        let since = self.sampled_at.elapsed();
+        if since >= RATE_INTERVAL {
+            self.rate = processed.saturating_sub(self.sampled) as f64 / since.as_secs_f64();
+            self.sampled = processed;
+            self.sampled_at = Instant::now();
+        }
+        let groups = self.app.get_groups_ref().read().len();
+        let elapsed = metrics.elapsed().as_secs();
+        let status = format!(
+            " {:.0} lines/s | {} lines | {} groups | queue {}/{} | filtered {} | {} | up {:02}:{:02}:{:02}",
+            self.rate,
+            processed,
+            groups,
+            metrics.queue_depth(),
+            metrics.queue_capacity(),
+            metrics.filtered(),
+            sources(&metrics.source_states()),
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60,
+        );
+        let bar = Paragraph::new(status).style(Style::default().fg(Color::Black).bg(Color::Gray));
+        f.render_widget(bar, area);
+    }
+}
+
+/// State of a single source, or how many sources are in each state when there are several
+fn sources(states: &BTreeMap<Arc<str>, SourceState>) -> String {
+    match states.len() {
+        0 => "starting".to_string(),
+        1 => {
+            let (source, state) = states.iter().next().expect("length checked");
+            format!("{}: {}", source, state)
+        },
+        n => {
+            let counts = states
+                .values()
+                .counts()
+                .into_iter()
+                .sorted()
+                .map(|(state, count)| format!("{} {}", count, state))
+                .join(", ");
+            format!("{} sources: {}", n, counts)
+        },
+    }
+}